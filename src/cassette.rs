@@ -0,0 +1,436 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::warn;
+use crate::client::Client;
+use crate::dispatch::Dispatcher;
+use crate::types::{Update, User};
+
+// How long replay waits for a handler to make the calls the recording expects,
+// and how long it keeps listening for extra calls after that.
+const SETTLE_TIMEOUT: Duration = Duration::from_secs(5);
+const SETTLE_QUIET: Duration = Duration::from_millis(200);
+
+tokio::task_local! {
+    static UPDATE_ID: i64;
+}
+
+/// Runs `fut` as the handling of update `update_id`: the API calls it makes are
+/// recorded against that update, even when they happen after later updates
+/// arrived. The dispatcher carries the id over to the handler tasks it spawns.
+pub async fn for_update<F: Future>(update_id: i64, fut: F) -> F::Output {
+    UPDATE_ID.scope(update_id, fut).await
+}
+
+/// The update the current task is handling, if any.
+pub fn current_update() -> Option<i64> {
+    UPDATE_ID.try_with(|id| *id).ok()
+}
+
+/// One line of a cassette: an incoming update, or an outgoing API call with the
+/// response Telegram gave to it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    Update {
+        update: Value,
+    },
+    Call {
+        method: String,
+        params: Value,
+        status: u16,
+        response: Value,
+        /// The update whose handler made the call; absent for startup calls.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        update_id: Option<i64>,
+    },
+}
+
+enum Sink {
+    /// Lines for the writer thread, so that recording never blocks the runtime on disk I/O.
+    File(mpsc::Sender<String>),
+    Memory(Vec<Entry>),
+}
+
+/// Appends the raw traffic of a `Client` to a JSONL cassette. Every request other
+/// than getUpdates, retries included, is stored as a `call` entry. Updates are
+/// held back until the bot starts handling them (`begin_update`); calls carry
+/// the id of the update they were made for.
+pub struct Recorder {
+    sink: Mutex<Sink>,
+    pending: Mutex<HashMap<i64, Value>>,
+}
+
+impl Recorder {
+    pub async fn to_file(path: impl AsRef<Path>) -> io::Result<Arc<Self>> {
+        if let Some(dir) = path.as_ref().parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?.into_std().await;
+        let (tx, rx) = mpsc::channel::<String>();
+        std::thread::Builder::new().name("cassette-writer".into()).spawn(move || {
+            for line in rx {
+                if let Err(e) = writeln!(file, "{}", line) {
+                    warn!("failed to write cassette entry: {}", e);
+                }
+            }
+        })?;
+        Ok(Arc::new(Self { sink: Mutex::new(Sink::File(tx)), pending: Default::default() }))
+    }
+
+    pub fn in_memory() -> Arc<Self> {
+        Arc::new(Self { sink: Mutex::new(Sink::Memory(Vec::new())), pending: Default::default() })
+    }
+
+    /// Entries kept by an in-memory recorder; empty for a file recorder.
+    pub fn entries(&self) -> Vec<Entry> {
+        match &*self.sink.lock().unwrap() {
+            Sink::Memory(entries) => entries.clone(),
+            Sink::File(_) => Vec::new(),
+        }
+    }
+
+    pub fn record(&self, entry: Entry) {
+        let mut sink = self.sink.lock().unwrap();
+        match &mut *sink {
+            Sink::Memory(entries) => entries.push(entry),
+            Sink::File(tx) => {
+                let line = match serde_json::to_string(&entry) {
+                    Ok(l) => l,
+                    Err(e) => return warn!("failed to encode cassette entry: {}", e),
+                };
+                if tx.send(line).is_err() {
+                    warn!("cassette writer stopped, entry dropped");
+                }
+            }
+        }
+    }
+
+    /// Records one HTTP exchange made by the client.
+    pub fn record_exchange(&self, method: &str, params: Value, status: u16, body: &str) {
+        let response = serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string()));
+        if method == "getUpdates" {
+            if let Some(updates) = response.get("result").and_then(|r| r.as_array()) {
+                let mut pending = self.pending.lock().unwrap();
+                for update in updates {
+                    if let Some(id) = update.get("update_id").and_then(|v| v.as_i64()) {
+                        pending.insert(id, update.clone());
+                    }
+                }
+            }
+            return;
+        }
+        self.record(Entry::Call { method: method.to_string(), params, status, response, update_id: current_update() });
+    }
+
+    /// Writes the raw JSON of update `update_id`, as received from getUpdates.
+    pub fn begin_update(&self, update_id: i64) {
+        let update = self.pending.lock().unwrap().remove(&update_id);
+        if let Some(update) = update {
+            self.record(Entry::Update { update });
+        }
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> io::Result<Vec<Entry>> {
+    let file = std::fs::File::open(path)?;
+    let mut entries = Vec::new();
+    for (i, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", i + 1, e)))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Outgoing calls that differ from the recording for one update.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub update_id: i64,
+    pub expected: Vec<(String, Value)>,
+    pub actual: Vec<(String, Value)>,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub updates: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl ReplayReport {
+    pub fn is_clean(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "replayed {} update(s), {} mismatch(es)", self.updates, self.mismatches.len())?;
+        for m in &self.mismatches {
+            writeln!(f, "update {}:", m.update_id)?;
+            for i in 0..m.expected.len().max(m.actual.len()) {
+                let expected = m.expected.get(i);
+                let actual = m.actual.get(i);
+                if expected == actual {
+                    continue;
+                }
+                if let Some((method, params)) = expected {
+                    writeln!(f, "  - {} {}", method, params)?;
+                }
+                if let Some((method, params)) = actual {
+                    writeln!(f, "  + {} {}", method, params)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn update_id_of(update: &Value) -> i64 {
+    update.get("update_id").and_then(|v| v.as_i64()).unwrap_or(0)
+}
+
+/// The updates of a cassette, each with the calls the bot made in response.
+/// Calls without an update id, from startup or from cassettes recorded before
+/// calls carried one, belong to the update entry before them, if any.
+fn segments(entries: &[Entry]) -> Vec<(Value, Vec<(String, Value)>)> {
+    let mut out: Vec<(Value, Vec<(String, Value)>)> = Vec::new();
+    for entry in entries {
+        match entry {
+            Entry::Update { update } => out.push((update.clone(), Vec::new())),
+            Entry::Call { method, params, update_id, .. } => {
+                let segment = match update_id {
+                    Some(id) => out.iter_mut().rev().find(|(u, _)| update_id_of(u) == *id),
+                    None => out.last_mut(),
+                };
+                if let Some((_, calls)) = segment {
+                    calls.push((method.clone(), params.clone()));
+                }
+            }
+        }
+    }
+    out
+}
+
+/// The calls recorded for update `update_id`.
+fn calls_for(entries: &[Entry], update_id: i64) -> Vec<(String, Value)> {
+    entries
+        .iter()
+        .filter_map(|e| match e {
+            Entry::Call { method, params, update_id: Some(id), .. } if *id == update_id => Some((method.clone(), params.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Answers each method with the responses recorded for it, in order. Methods
+/// that run out of recorded responses get `{"ok":true,"result":true}`.
+struct Stub {
+    responses: Mutex<HashMap<String, VecDeque<(u16, Value)>>>,
+}
+
+async fn stub_handle(stub: Arc<Stub>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = req.uri().path().rsplit('/').next().unwrap_or("").to_string();
+    let (status, body) = stub
+        .responses
+        .lock()
+        .unwrap()
+        .get_mut(&method)
+        .and_then(|q| q.pop_front())
+        .unwrap_or_else(|| (200, serde_json::json!({ "ok": true, "result": true })));
+    let body = match body {
+        Value::String(s) => s,
+        other => other.to_string(),
+    };
+    let mut resp = Response::new(Body::from(body));
+    *resp.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::OK);
+    Ok(resp)
+}
+
+/// Feeds the updates of a cassette through `disp` against a stub API that gives
+/// the recorded responses, and compares the calls the handlers make with the
+/// recorded ones, update by update.
+pub async fn replay(entries: &[Entry], disp: &Dispatcher) -> io::Result<ReplayReport> {
+    let mut responses: HashMap<String, VecDeque<(u16, Value)>> = HashMap::new();
+    let mut me: Option<User> = None;
+    for entry in entries {
+        if let Entry::Call { method, status, response, .. } = entry {
+            if method == "getMe" && me.is_none() {
+                me = response.get("result").and_then(|r| serde_json::from_value(r.clone()).ok());
+            }
+            responses.entry(method.clone()).or_default().push_back((*status, response.clone()));
+        }
+    }
+
+    let stub = Arc::new(Stub { responses: Mutex::new(responses) });
+    let incoming = AddrIncoming::bind(&([127, 0, 0, 1], 0).into()).map_err(io::Error::other)?;
+    let url = format!("http://{}", incoming.local_addr());
+    let make_svc = make_service_fn(move |_conn| {
+        let stub = stub.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| stub_handle(stub.clone(), req))) }
+    });
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    tokio::spawn(Server::builder(incoming).serve(make_svc).with_graceful_shutdown(async {
+        let _ = stop_rx.await;
+    }));
+
+    let mut client = Client::builder("replay:cassette").api_url(url).build();
+    client.rate_limiter = None;
+    client.chat_limiter = None;
+    let recorder = Recorder::in_memory();
+    client.recorder = Some(recorder.clone());
+    if let Some(me) = me {
+        client.set_me(me);
+    }
+
+    let mut report = ReplayReport::default();
+    for (raw, expected) in segments(entries) {
+        let update_id = update_id_of(&raw);
+        let update: Update = match serde_json::from_value(raw) {
+            Ok(u) => u,
+            Err(e) => {
+                warn!("skipping update {} that no longer parses: {}", update_id, e);
+                continue;
+            }
+        };
+        report.updates += 1;
+        disp.dispatch_update(client.clone(), update).await;
+        let actual = settle(&recorder, update_id, expected.len()).await;
+        if actual != expected {
+            report.mismatches.push(Mismatch { update_id, expected, actual });
+        }
+    }
+    let _ = stop_tx.send(());
+    Ok(report)
+}
+
+/// Waits for the handlers spawned for one update to finish their calls: until
+/// `expected` calls arrived and no more follow, or the timeout passes.
+async fn settle(recorder: &Recorder, update_id: i64, expected: usize) -> Vec<(String, Value)> {
+    let started = Instant::now();
+    let mut seen = 0;
+    let mut last_change = Instant::now();
+    loop {
+        let calls = calls_for(&recorder.entries(), update_id);
+        if calls.len() != seen {
+            seen = calls.len();
+            last_change = Instant::now();
+        }
+        let quiet = last_change.elapsed() >= SETTLE_QUIET;
+        if (seen >= expected && quiet) || started.elapsed() >= SETTLE_TIMEOUT {
+            return calls;
+        }
+        sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockBotApi;
+
+    fn dispatcher(reply: &'static str) -> Dispatcher {
+        let mut disp = Dispatcher::new();
+        disp.add_command("ping", move |client: Client, msg: crate::types::Message| async move {
+            client.send_message(msg.chat.id, reply, None).await?;
+            Ok(())
+        });
+        disp
+    }
+
+    async fn record_session() -> Vec<Entry> {
+        let api = MockBotApi::start().await;
+        let mut client = api.client();
+        let recorder = Recorder::in_memory();
+        client.recorder = Some(recorder.clone());
+        client.set_me(client.get_me().await.unwrap());
+        api.push_message(42, 42, "/ping");
+        api.push_message(42, 42, "hello");
+
+        let disp = dispatcher("pong");
+        for (u, calls) in client.get_updates(0, 0, &[]).await.unwrap().into_iter().zip([1, 0]) {
+            let update_id = u.update_id;
+            recorder.begin_update(update_id);
+            disp.dispatch_update(client.clone(), u).await;
+            settle(&recorder, update_id, calls).await;
+        }
+        recorder.entries()
+    }
+
+    #[tokio::test]
+    async fn attributes_calls_to_their_update() {
+        let api = MockBotApi::start().await;
+        let mut client = api.client();
+        let recorder = Recorder::in_memory();
+        client.recorder = Some(recorder.clone());
+        let mut disp = dispatcher("pong");
+        disp.add_command("slow", |client: Client, msg: crate::types::Message| async move {
+            sleep(Duration::from_millis(200)).await;
+            client.send_message(msg.chat.id, "done", None).await?;
+            Ok(())
+        });
+        api.push_message(42, 42, "/slow");
+        api.push_message(42, 42, "/ping");
+
+        // The slow handler answers after the second update was dispatched.
+        let mut ids = Vec::new();
+        for u in client.get_updates(0, 0, &[]).await.unwrap() {
+            ids.push(u.update_id);
+            recorder.begin_update(u.update_id);
+            disp.dispatch_update(client.clone(), u).await;
+        }
+        for id in ids {
+            settle(&recorder, id, 1).await;
+        }
+        let texts: Vec<Vec<Value>> = segments(&recorder.entries())
+            .into_iter()
+            .map(|(_, calls)| calls.into_iter().filter(|(m, _)| m == "sendMessage").map(|(_, p)| p["text"].clone()).collect())
+            .collect();
+        assert_eq!(texts, vec![vec![Value::from("done")], vec![Value::from("pong")]]);
+    }
+
+    #[tokio::test]
+    async fn records_updates_and_calls() {
+        let entries = record_session().await;
+        let kinds: Vec<&str> = entries
+            .iter()
+            .map(|e| match e {
+                Entry::Update { .. } => "update",
+                Entry::Call { method, .. } => method.as_str(),
+            })
+            .collect();
+        assert_eq!(kinds, vec!["getMe", "update", "sendMessage", "update"]);
+    }
+
+    #[tokio::test]
+    async fn replay_matches_unchanged_handlers() {
+        let entries = record_session().await;
+        let report = replay(&entries, &dispatcher("pong")).await.unwrap();
+        assert_eq!(report.updates, 2);
+        assert!(report.is_clean(), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn replay_reports_changed_output() {
+        let entries = record_session().await;
+        let report = replay(&entries, &dispatcher("PONG")).await.unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].actual[0].1["text"], "PONG");
+        assert!(report.to_string().contains("- sendMessage"));
+    }
+}
@@ -1,3 +1,7 @@
+mod chunk;
 mod http;
+mod input_file;
+pub mod requests;
 
 pub use http::{Client, BotError};
+pub use input_file::InputFile;
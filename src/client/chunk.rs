@@ -0,0 +1,417 @@
+use unicode_segmentation::UnicodeSegmentation;
+use crate::types::ParseMode;
+use crate::utils::utf16_len;
+
+/// A piece of message source. Text tokens are what Telegram counts towards the
+/// length limit; markup tokens have zero visible length.
+#[derive(Debug, Clone)]
+enum Token<'a> {
+    Text { raw: &'a str, len: usize },
+    Open { raw: String, close: String },
+    Close { raw: &'a str },
+}
+
+/// Reopens a MarkdownV2 block quote line that was cut by a chunk boundary.
+const QUOTE: &str = ">";
+
+/// Telegram's message length limit, in UTF-16 code units after entity parsing.
+pub const MAX_MESSAGE_LEN: usize = 4096;
+
+/// Visible length of a piece of text once Telegram has parsed the entities.
+fn text_len(s: &str) -> usize {
+    utf16_len(s)
+}
+
+/// Visible length of an HTML entity body such as `lt` or `#128512`.
+fn html_entity_len(name: &str) -> usize {
+    let code = if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(dec) = name.strip_prefix('#') {
+        dec.parse().ok()
+    } else {
+        None
+    };
+    code.and_then(char::from_u32).map(|c| c.len_utf16()).unwrap_or(1)
+}
+
+fn push_text<'a>(tokens: &mut Vec<Token<'a>>, s: &'a str) {
+    for g in s.graphemes(true) {
+        tokens.push(Token::Text { raw: g, len: text_len(g) });
+    }
+}
+
+fn html_tag_name(tag: &str) -> String {
+    tag.trim_start_matches('<')
+        .trim_start_matches('/')
+        .trim_end_matches('>')
+        .split(|c: char| c.is_whitespace())
+        .next()
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+fn tokenize_html(s: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = s;
+    while !rest.is_empty() {
+        let next = rest.find(['<', '&']).unwrap_or(rest.len());
+        if next > 0 {
+            push_text(&mut tokens, &rest[..next]);
+            rest = &rest[next..];
+            continue;
+        }
+        if rest.starts_with('<') {
+            if let Some(end) = rest.find('>') {
+                let tag = &rest[..=end];
+                if tag.starts_with("</") {
+                    tokens.push(Token::Close { raw: tag });
+                } else {
+                    tokens.push(Token::Open { raw: tag.to_string(), close: format!("</{}>", html_tag_name(tag)) });
+                }
+                rest = &rest[end + 1..];
+                continue;
+            }
+        } else if let Some(end) = rest[1..].find(';').map(|i| i + 1) {
+            let name = &rest[1..end];
+            let valid = !name.is_empty()
+                && name.len() <= 10
+                && (name.chars().all(|c| c.is_ascii_alphanumeric()) || (name.starts_with('#') && name[1..].chars().all(|c| c.is_ascii_alphanumeric())));
+            if valid {
+                // An entity such as `&lt;` is one character after parsing and must not be split.
+                tokens.push(Token::Text { raw: &rest[..=end], len: html_entity_len(name) });
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        let g = rest.graphemes(true).next().unwrap_or(rest);
+        push_text(&mut tokens, g);
+        rest = &rest[g.len()..];
+    }
+    tokens
+}
+
+/// Finds the `](url)` that closes a link opened at the start of `s`, honouring escapes.
+fn markdown_link_close(s: &str) -> Option<(usize, usize)> {
+    let bytes = s.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b']' if bytes.get(i + 1) == Some(&b'(') => {
+                let mut j = i + 2;
+                while j < bytes.len() {
+                    match bytes[j] {
+                        b'\\' => j += 2,
+                        b')' => return Some((i, j + 1)),
+                        _ => j += 1,
+                    }
+                }
+                return None;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+fn tokenize_markdown(s: &str, v2: bool) -> Vec<Token<'_>> {
+    let markers: &[&str] = if v2 { &["||", "__", "*", "_", "~"] } else { &["*", "_"] };
+    let mut tokens = Vec::new();
+    let mut open: Vec<String> = Vec::new();
+    let mut link_ends: Vec<(usize, usize)> = Vec::new();
+    let mut rest = s;
+    let mut in_code: Option<&str> = None;
+    let mut line_start = true;
+    let mut in_quote = false;
+
+    while !rest.is_empty() {
+        let offset = s.len() - rest.len();
+
+        if rest.starts_with('\\') && rest.len() > 1 {
+            let g = rest[1..].graphemes(true).next().unwrap_or("");
+            let raw = &rest[..1 + g.len()];
+            tokens.push(Token::Text { raw, len: text_len(g) });
+            rest = &rest[raw.len()..];
+            line_start = false;
+            continue;
+        }
+
+        if let Some(fence) = in_code {
+            if rest.starts_with(fence) {
+                tokens.push(Token::Close { raw: &rest[..fence.len()] });
+                rest = &rest[fence.len()..];
+                in_code = None;
+                continue;
+            }
+        } else if rest.starts_with("```") {
+            // The opening fence carries the optional language up to the end of the line.
+            let head_len = rest[3..].find('\n').map(|i| i + 4).unwrap_or(3);
+            let head = &rest[..head_len];
+            tokens.push(Token::Open { raw: head.to_string(), close: "```".to_string() });
+            rest = &rest[head_len..];
+            in_code = Some("```");
+            continue;
+        } else if rest.starts_with('`') {
+            tokens.push(Token::Open { raw: "`".to_string(), close: "`".to_string() });
+            rest = &rest[1..];
+            in_code = Some("`");
+            continue;
+        } else if v2 && line_start && (rest.starts_with("**>") || rest.starts_with('>')) {
+            // Each quoted line carries its own `>`, so the quote is an entity that
+            // ends with the line; a chunk boundary inside the line reopens it with `>`.
+            if rest.starts_with("**") {
+                tokens.push(Token::Text { raw: "**", len: 0 });
+                rest = &rest[2..];
+            }
+            tokens.push(Token::Open { raw: QUOTE.to_string(), close: String::new() });
+            rest = &rest[1..];
+            in_quote = true;
+            line_start = false;
+            continue;
+        } else if v2 && in_quote && rest.starts_with("||") && open.last().map(|o| o != "||").unwrap_or(true) && matches!(rest[2..].chars().next(), None | Some('\n')) {
+            // The mark that ends an expandable quote, not a spoiler.
+            tokens.push(Token::Text { raw: &rest[..2], len: 0 });
+            rest = &rest[2..];
+            continue;
+        } else if in_quote && rest.starts_with('\n') {
+            tokens.push(Token::Close { raw: "" });
+            in_quote = false;
+        } else if let Some(&(at, close_len)) = link_ends.last().filter(|(at, _)| *at == offset) {
+            tokens.push(Token::Close { raw: &s[at..at + close_len] });
+            rest = &rest[close_len..];
+            link_ends.pop();
+            continue;
+        } else if rest.starts_with('[') {
+            if let Some((close_start, close_end)) = markdown_link_close(rest) {
+                // The link is reopened with the same `](url)` if a chunk boundary falls inside it.
+                let close = rest[close_start..close_end].to_string();
+                tokens.push(Token::Open { raw: "[".to_string(), close });
+                link_ends.push((offset + close_start, close_end - close_start));
+                rest = &rest[1..];
+                continue;
+            }
+        } else if let Some(m) = markers.iter().find(|m| rest.starts_with(**m)) {
+            if open.last().map(|o| o == m).unwrap_or(false) {
+                open.pop();
+                tokens.push(Token::Close { raw: &rest[..m.len()] });
+            } else {
+                open.push(m.to_string());
+                tokens.push(Token::Open { raw: m.to_string(), close: m.to_string() });
+            }
+            rest = &rest[m.len()..];
+            line_start = false;
+            continue;
+        }
+
+        let g = rest.graphemes(true).next().unwrap_or(rest);
+        push_text(&mut tokens, g);
+        line_start = g == "\n";
+        rest = &rest[g.len()..];
+    }
+    tokens
+}
+
+fn tokenize(s: &str, parse_mode: Option<ParseMode>) -> Vec<Token<'_>> {
+    match parse_mode {
+        Some(ParseMode::Html) => tokenize_html(s),
+        Some(ParseMode::MarkdownV2) => tokenize_markdown(s, true),
+        Some(ParseMode::Markdown) => tokenize_markdown(s, false),
+        None => {
+            let mut tokens = Vec::new();
+            push_text(&mut tokens, s);
+            tokens
+        }
+    }
+}
+
+/// Where the current chunk could end at a line break, and the state at that point.
+struct LineBreak {
+    at: usize,
+    stack: Vec<(String, String)>,
+    out_len: usize,
+    has_text: bool,
+}
+
+fn closing(stack: &[(String, String)]) -> String {
+    stack.iter().rev().map(|(_, close)| close.as_str()).collect()
+}
+
+/// The markup that reopens `stack` at the start of a chunk. A block quote has to
+/// come first, since `>` only starts a quote at the beginning of a line.
+fn reopening(stack: &[(String, String)]) -> String {
+    let (quotes, rest): (Vec<_>, Vec<_>) = stack.iter().partition(|(open, _)| open == QUOTE);
+    quotes.into_iter().chain(rest).map(|(open, _)| open.as_str()).collect()
+}
+
+/// Splits `s` into messages of at most `max_len` UTF-16 code units of visible
+/// text, never inside a grapheme cluster. Markup is never cut: entities that
+/// span a boundary are closed at the end of one chunk and reopened at the start
+/// of the next. Splits prefer line breaks.
+pub fn chunk_message(s: &str, max_len: usize, parse_mode: Option<ParseMode>) -> Vec<String> {
+    let tokens = tokenize(s, parse_mode);
+    let total: usize = tokens.iter().map(|t| if let Token::Text { len, .. } = t { *len } else { 0 }).sum();
+    if total <= max_len {
+        return vec![s.to_string()];
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut stack: Vec<(String, String)> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let mut out = reopening(&stack);
+        let mut len = 0usize;
+        let mut has_text = false;
+        let mut last_break: Option<LineBreak> = None;
+        let mut j = i;
+
+        while j < tokens.len() {
+            match &tokens[j] {
+                Token::Text { raw, len: l } => {
+                    if len + l > max_len && len > 0 {
+                        break;
+                    }
+                    out.push_str(raw);
+                    len += l;
+                    has_text |= *l > 0 && !raw.trim().is_empty();
+                    j += 1;
+                    if *raw == "\n" {
+                        last_break = Some(LineBreak { at: j, stack: stack.clone(), out_len: out.len(), has_text });
+                    }
+                }
+                Token::Open { raw, close } => {
+                    out.push_str(raw);
+                    stack.push((raw.clone(), close.clone()));
+                    j += 1;
+                }
+                Token::Close { raw } => {
+                    out.push_str(raw);
+                    // Close the innermost matching entity; entities that overlap,
+                    // such as bold running across quoted lines, need not be on top.
+                    match stack.iter().rposition(|(_, close)| close.eq_ignore_ascii_case(raw)) {
+                        Some(at) => {
+                            stack.remove(at);
+                        }
+                        None => {
+                            stack.pop();
+                        }
+                    }
+                    j += 1;
+                }
+            }
+        }
+
+        if j < tokens.len() {
+            if let Some(b) = last_break.filter(|b| b.has_text) {
+                j = b.at;
+                stack = b.stack;
+                out.truncate(b.out_len);
+            } else {
+                // Entities opened right before the boundary start the next chunk
+                // instead of leaving an empty pair such as `<b></b>` behind.
+                while j > i {
+                    let Token::Open { raw, .. } = &tokens[j - 1] else { break };
+                    out.truncate(out.len() - raw.len());
+                    stack.pop();
+                    j -= 1;
+                    // Keep `**>` together.
+                    if raw == QUOTE && j > i && matches!(tokens[j - 1], Token::Text { raw: "**", len: 0 }) {
+                        out.truncate(out.len() - 2);
+                        j -= 1;
+                    }
+                }
+            }
+        }
+        out.push_str(&closing(&stack));
+        // A chunk with nothing but whitespace would be rejected as an empty message;
+        // its markup is balanced, so it can be dropped.
+        if has_text {
+            parts.push(out);
+        }
+        i = j;
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(s: &str, max_len: usize, mode: Option<ParseMode>) -> Vec<String> {
+        let parts = chunk_message(s, max_len, mode);
+        for p in &parts {
+            let visible: usize = tokenize(p, mode).iter().map(|t| if let Token::Text { len, .. } = t { *len } else { 0 }).sum();
+            assert!(visible <= max_len || p.graphemes(true).count() == 1, "{:?} is {} long", p, visible);
+        }
+        parts
+    }
+
+    #[test]
+    fn reopens_html_tags_across_a_split() {
+        let parts = chunks("<b>bold <i>and italic</i></b> tail", 8, Some(ParseMode::Html));
+        assert_eq!(parts, vec!["<b>bold <i>and</i></b>", "<b><i> italic</i></b> ", "tail"]);
+        let parts = chunks(r#"<a href="https://example.com">link text</a>"#, 5, Some(ParseMode::Html));
+        assert_eq!(parts, vec![r#"<a href="https://example.com">link </a>"#, r#"<a href="https://example.com">text</a>"#]);
+    }
+
+    #[test]
+    fn keeps_html_entities_whole() {
+        let parts = chunks("&lt;&lt;&lt;&#128512;&#x41;&amp;", 2, Some(ParseMode::Html));
+        assert_eq!(parts, vec!["&lt;&lt;", "&lt;", "&#128512;", "&#x41;&amp;"]);
+    }
+
+    #[test]
+    fn keeps_markdown_v2_escapes_links_and_fences() {
+        let parts = chunks(r"a\.b\.c\.d\!", 3, Some(ParseMode::MarkdownV2));
+        assert_eq!(parts, vec![r"a\.b", r"\.c\.", r"d\!"]);
+
+        let parts = chunks(r"see [the docs here](https://example.com/a\)b) now", 6, Some(ParseMode::MarkdownV2));
+        assert_eq!(parts, vec![
+            r"see [th](https://example.com/a\)b)",
+            r"[e docs](https://example.com/a\)b)",
+            r"[ here](https://example.com/a\)b) ",
+            "now",
+        ]);
+
+        let parts = chunks("```rust\nlet a = 1;\nlet b = 2;\n```", 12, Some(ParseMode::MarkdownV2));
+        assert_eq!(parts, vec!["```rust\nlet a = 1;\n```", "```rust\nlet b = 2;\n```"]);
+    }
+
+    #[test]
+    fn continues_block_quotes() {
+        let parts = chunks(">quoted line here\n>second line", 8, Some(ParseMode::MarkdownV2));
+        assert_eq!(parts, vec![">quoted l", ">ine here", "\n>second ", ">line"]);
+        let parts = chunks("**>hidden quote text\n>more lines here||", 8, Some(ParseMode::MarkdownV2));
+        assert_eq!(parts, vec!["**>hidden q", ">uote tex", ">t\n", ">more lin", ">es here||"]);
+        // A spoiler at the end of a quoted line is still a spoiler.
+        let parts = chunks(">a ||secret||\n>b", 20, Some(ParseMode::MarkdownV2));
+        assert_eq!(parts, vec![">a ||secret||\n>b"]);
+        assert_eq!(chunks(">a ||secret words||", 9, Some(ParseMode::MarkdownV2)), vec![">a ||secret ||", ">||words||"]);
+    }
+
+    #[test]
+    fn leaves_no_empty_entities_at_a_split() {
+        assert_eq!(chunks("abcd<b>efgh</b>", 4, Some(ParseMode::Html)), vec!["abcd", "<b>efgh</b>"]);
+        assert_eq!(chunks("abcd*_efgh_*", 4, Some(ParseMode::MarkdownV2)), vec!["abcd", "*_efgh_*"]);
+        assert_eq!(chunks("   \n**>efgh", 4, Some(ParseMode::MarkdownV2)), vec!["**>efgh"]);
+    }
+
+    #[test]
+    fn prefers_line_breaks() {
+        assert_eq!(chunks("aaa bbb\nccc ddd eee", 10, None), vec!["aaa bbb\n", "ccc ddd ee", "e"]);
+        assert_eq!(chunks("<b>one\ntwo three</b>", 9, Some(ParseMode::Html)), vec!["<b>one\n</b>", "<b>two three</b>"]);
+    }
+
+    #[test]
+    fn drops_whitespace_only_chunks() {
+        assert_eq!(chunks("aaaa\n    \n    \nbbbb", 4, None), vec!["aaaa", "  \nb", "bbb"]);
+        assert_eq!(chunks("aaaa        bbbb", 4, None), vec!["aaaa", "bbbb"]);
+    }
+
+    #[test]
+    fn never_splits_a_grapheme() {
+        // The family emoji is one grapheme of 8 UTF-16 units; it gets a chunk of its own.
+        assert_eq!(chunks("ab👨‍👩‍👧cd", 3, None), vec!["ab", "👨‍👩‍👧", "cd"]);
+        assert_eq!(chunks("e\u{301}e\u{301}", 1, None), vec!["e\u{301}", "e\u{301}"]);
+        assert_eq!(chunks("short", MAX_MESSAGE_LEN, None), vec!["short"]);
+    }
+}
@@ -1,20 +1,39 @@
 use reqwest::Client as HttpClient;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use crate::types::{ApiResponse, Update, File, UserProfilePhotos};
+use crate::types::{ApiResponse, ResponseParameters, Update, File, UserProfilePhotos, WebhookInfo, ChatFullInfo, Message, ParseMode, ReplyMarkup};
+use crate::types::{BotCommand, BotCommandScope, CallbackQuery, EditResult, InlineKeyboardMarkup, Poll, ShippingOption, UpdateKind, User};
+use super::InputFile;
+use crate::format::Text;
+use crate::cassette::Recorder;
+use crate::metrics;
+use super::chunk::{chunk_message, MAX_MESSAGE_LEN};
+use super::requests::{
+    AnswerCallbackQuery, AnswerInlineQuery, AnswerPreCheckoutQuery, AnswerShippingQuery, CreateInvoiceLink, DeleteMessage, DeleteMessages, DeleteMyCommands, DeleteWebhook, EditMessageCaption, EditMessageReplyMarkup,
+    EditMessageText, GetChat, GetFile, GetMe, GetUpdates, GetUserProfilePhotos, GetWebhookInfo, InvoiceParams, RefundStarPayment, Request,
+    SendAnimation, SendAudio, SendDocument, SendInvoice, SendMessage, SendPhoto, SendPoll, SendSticker, SendVideo, SendVideoNote, SendVoice,
+    SetMyCommands, SetWebhook, StopPoll,
+};
 use thiserror::Error;
 use reqwest::multipart::{Form, Part};
 use tokio::fs;
 use std::path::Path;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
 use tracing::{warn};
 use tokio_util::io::ReaderStream;
 
 use tokio::sync::{Mutex, Notify};
+use std::collections::{HashMap, VecDeque};
 
-fn max_upload_bytes() -> u64 {
-    std::env::var("MAX_UPLOAD_MB").ok().and_then(|s| s.parse().ok()).unwrap_or(50) * 1024 * 1024
-}
+pub const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+// Slack on top of the long-poll timeout before a getUpdates request is abandoned.
+const POLL_TIMEOUT_MARGIN: Duration = Duration::from_secs(10);
+
+// api.telegram.org caps bot uploads at 50 MB; a self-hosted telegram-bot-api
+// server accepts up to 2000 MB.
+const CLOUD_MAX_UPLOAD_MB: u64 = 50;
+const LOCAL_MAX_UPLOAD_MB: u64 = 2000;
 
 #[derive(Error, Debug)]
 pub enum BotError {
@@ -22,17 +41,158 @@ pub enum BotError {
     Http(#[from] reqwest::Error),
     #[error("api error: {0}")]
     Api(String),
+    #[error("telegram error {code}: {description}")]
+    Telegram {
+        code: i32,
+        description: String,
+        parameters: Option<ResponseParameters>,
+    },
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 }
 
+#[allow(dead_code)]
+impl BotError {
+    fn telegram(api: ApiResponse<serde_json::Value>, status: u16) -> Self {
+        BotError::Telegram {
+            code: api.error_code.unwrap_or(status as i32),
+            description: api.description.unwrap_or_else(|| "telegram api error".into()),
+            parameters: api.parameters,
+        }
+    }
+
+    /// The Bot API `error_code`, if this error came from Telegram.
+    pub fn error_code(&self) -> Option<i32> {
+        match self {
+            BotError::Telegram { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            BotError::Telegram { description, .. } => Some(description),
+            _ => None,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            BotError::Telegram { parameters: Some(p), .. } => p.retry_after,
+            _ => None,
+        }
+    }
+
+    pub fn migrate_to_chat_id(&self) -> Option<i64> {
+        match self {
+            BotError::Telegram { parameters: Some(p), .. } => p.migrate_to_chat_id,
+            _ => None,
+        }
+    }
+
+    // Telegram only distinguishes these cases by description, so match on the
+    // code first and the stable part of the text second.
+    fn matches(&self, code: i32, needle: &str) -> bool {
+        match self {
+            BotError::Telegram { code: c, description, .. } => *c == code && description.to_lowercase().contains(needle),
+            _ => false,
+        }
+    }
+
+    pub fn is_too_many_requests(&self) -> bool {
+        self.error_code() == Some(429)
+    }
+
+    pub fn is_blocked_by_user(&self) -> bool {
+        self.matches(403, "bot was blocked by the user")
+    }
+
+    pub fn is_user_deactivated(&self) -> bool {
+        self.matches(403, "user is deactivated")
+    }
+
+    pub fn is_kicked_from_chat(&self) -> bool {
+        self.matches(403, "bot was kicked")
+    }
+
+    pub fn is_chat_not_found(&self) -> bool {
+        self.matches(400, "chat not found")
+    }
+
+    pub fn is_message_not_modified(&self) -> bool {
+        self.matches(400, "message is not modified")
+    }
+
+    pub fn is_message_to_edit_not_found(&self) -> bool {
+        self.matches(400, "message to edit not found")
+    }
+}
+
+enum Payload<'a> {
+    Json(serde_json::Value),
+    Multipart {
+        fields: Vec<(String, String)>,
+        files: Vec<(&'static str, &'a InputFile)>,
+    },
+}
+
+impl Payload<'_> {
+    fn chat_id(&self) -> Option<i64> {
+        match self {
+            Payload::Json(v) => v.get("chat_id").and_then(|c| c.as_i64()),
+            Payload::Multipart { fields, .. } => fields.iter().find(|(k, _)| k == "chat_id").and_then(|(_, v)| v.parse().ok()),
+        }
+    }
+
+    /// The parameters as JSON, for recording; uploads appear as their file name.
+    fn to_value(&self) -> serde_json::Value {
+        match self {
+            Payload::Json(v) => v.clone(),
+            Payload::Multipart { fields, files } => {
+                let mut obj = serde_json::Map::new();
+                for (k, v) in fields {
+                    obj.insert(k.clone(), v.clone().into());
+                }
+                for (name, file) in files {
+                    obj.insert(name.to_string(), file.filename(name).into());
+                }
+                obj.into()
+            }
+        }
+    }
+
+    fn set_chat_id(&mut self, chat_id: i64) {
+        match self {
+            Payload::Json(v) => {
+                if let Some(obj) = v.as_object_mut() {
+                    obj.insert("chat_id".into(), chat_id.into());
+                }
+            }
+            Payload::Multipart { fields, .. } => {
+                for (k, v) in fields.iter_mut() {
+                    if k == "chat_id" {
+                        *v = chat_id.to_string();
+                    }
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Client {
     pub base: String,
+    pub file_base: String,
     pub http: HttpClient,
     pub rate_limiter: Option<std::sync::Arc<RateLimiter>>,
+    pub chat_limiter: Option<std::sync::Arc<ChatRateLimiter>>,
+    pub max_upload_bytes: u64,
+    pub request_timeout: Option<Duration>,
+    pub recorder: Option<std::sync::Arc<Recorder>>,
+    migrations: std::sync::Arc<std::sync::RwLock<HashMap<i64, i64>>>,
+    me: std::sync::Arc<std::sync::OnceLock<User>>,
 }
 
 impl Client {
@@ -40,26 +200,195 @@ impl Client {
         ClientBuilder::new(token)
     }
 
+    /// Remembers that group `from` was upgraded to supergroup `to`; later sends to
+    /// `from` go to `to` directly.
+    pub fn record_chat_migration(&self, from: i64, to: i64) {
+        if let Ok(mut m) = self.migrations.write() {
+            m.insert(from, to);
+        }
+    }
+
+    /// All migrations seen so far, from Telegram errors or service messages.
+    pub fn chat_migrations(&self) -> Vec<(i64, i64)> {
+        self.migrations.read().map(|m| m.iter().map(|(k, v)| (*k, *v)).collect()).unwrap_or_default()
+    }
+
+    fn resolve_chat_id(&self, chat_id: i64) -> Option<i64> {
+        self.migrations.read().ok().and_then(|m| m.get(&chat_id).copied())
+    }
+
+    /// Stores the bot's own account as returned by getMe. Only the first call has
+    /// an effect; clones of the client share the value.
+    pub fn set_me(&self, user: User) {
+        let _ = self.me.set(user);
+    }
+
+    pub fn me(&self) -> Option<&User> {
+        self.me.get()
+    }
+
+    pub fn bot_username(&self) -> Option<&str> {
+        self.me().and_then(|u| u.username.as_deref())
+    }
+
+    /// `https://t.me/<bot>?start=<payload>`, once the username is known.
+    #[allow(dead_code)]
+    pub fn deep_link(&self, payload: &str) -> Option<String> {
+        self.bot_username().map(|u| format!("https://t.me/{}?start={}", u, payload))
+    }
 }
 
 pub struct ClientBuilder {
     token: String,
     http_builder: reqwest::ClientBuilder,
+    api_url: String,
+    file_url: Option<String>,
+    max_upload_bytes: Option<u64>,
+    proxy: Option<String>,
+    proxy_auth: Option<(String, String)>,
+    root_certificates: Vec<Vec<u8>>,
+    request_timeout: Option<Duration>,
 }
 
 impl ClientBuilder {
     pub fn new(token: impl Into<String>) -> Self {
-        Self { token: token.into(), http_builder: reqwest::Client::builder() }
+        Self {
+            token: token.into(),
+            http_builder: reqwest::Client::builder(),
+            api_url: TELEGRAM_API_URL.to_string(),
+            file_url: None,
+            max_upload_bytes: None,
+            proxy: None,
+            proxy_auth: None,
+            root_certificates: Vec::new(),
+            request_timeout: None,
+        }
+    }
+
+    /// Root of the Bot API server, e.g. `http://localhost:8081` for a self-hosted
+    /// telegram-bot-api or a mock server. Defaults to `https://api.telegram.org`.
+    pub fn api_url(mut self, url: impl Into<String>) -> Self {
+        self.api_url = url.into();
+        self
+    }
+
+    /// Root used for file downloads. Defaults to the API root.
+    pub fn file_url(mut self, url: impl Into<String>) -> Self {
+        self.file_url = Some(url.into());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn max_upload_bytes(mut self, bytes: u64) -> Self {
+        self.max_upload_bytes = Some(bytes);
+        self
+    }
+
+    /// Sends all requests through a proxy: `http://`, `https://`, `socks5://` or
+    /// `socks5h://` (DNS resolved by the proxy).
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Credentials for the proxy, if they are not part of its URL.
+    pub fn proxy_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.proxy_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.http_builder = self.http_builder.connect_timeout(timeout);
+        self
+    }
+
+    /// Timeout for a whole request. getUpdates gets the long-poll `timeout` on top
+    /// of it, so a long poll is never cut short.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.http_builder = self.http_builder.pool_max_idle_per_host(max);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.http_builder = self.http_builder.pool_idle_timeout(timeout);
+        self
     }
 
+    /// Trusts an extra CA in PEM format, e.g. for a TLS-intercepting corporate proxy.
+    pub fn root_certificate_pem(mut self, pem: impl Into<Vec<u8>>) -> Self {
+        self.root_certificates.push(pem.into());
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.http_builder = self.http_builder.user_agent(user_agent.into());
+        self
+    }
+
+    /// Like `try_build`, but panics on an invalid proxy URL or certificate.
     pub fn build(self) -> Client {
-        let http = self.http_builder.build().expect("failed to build reqwest client");
-        let base = format!("https://api.telegram.org/bot{}", self.token);
-        
+        self.try_build().expect("failed to build reqwest client")
+    }
+
+    pub fn try_build(self) -> Result<Client, BotError> {
+        let mut http_builder = self.http_builder;
+        if let Some(url) = &self.proxy {
+            let mut url = reqwest::Url::parse(url).map_err(|e| BotError::Api(format!("invalid proxy url: {}", e)))?;
+            let mut basic_auth = None;
+            if let Some((user, pass)) = &self.proxy_auth {
+                // SOCKS credentials only travel in the URL; HTTP proxies take a header.
+                if url.scheme().starts_with("socks") {
+                    let _ = url.set_username(user);
+                    let _ = url.set_password(Some(pass));
+                } else {
+                    basic_auth = Some((user, pass));
+                }
+            }
+            let mut proxy = reqwest::Proxy::all(url)?;
+            if let Some((user, pass)) = basic_auth {
+                proxy = proxy.basic_auth(user, pass);
+            }
+            http_builder = http_builder.proxy(proxy);
+        }
+        for pem in &self.root_certificates {
+            http_builder = http_builder.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+        }
+        let http = http_builder.build()?;
+        let api_url = self.api_url.trim_end_matches('/').to_string();
+        let file_url = self.file_url.as_deref().map(|u| u.trim_end_matches('/').to_string()).unwrap_or_else(|| api_url.clone());
+        let base = format!("{}/bot{}", api_url, self.token);
+        let file_base = format!("{}/file/bot{}", file_url, self.token);
+
+        let default_mb = if api_url == TELEGRAM_API_URL { CLOUD_MAX_UPLOAD_MB } else { LOCAL_MAX_UPLOAD_MB };
+        let max_upload_bytes = self.max_upload_bytes.unwrap_or_else(|| {
+            std::env::var("MAX_UPLOAD_MB").ok().and_then(|s| s.parse().ok()).unwrap_or(default_mb) * 1024 * 1024
+        });
+
         let rps: u32 = std::env::var("RATE_LIMIT_RPS").ok().and_then(|s| s.parse().ok()).unwrap_or(20);
         let burst: u32 = std::env::var("RATE_LIMIT_BURST").ok().and_then(|s| s.parse().ok()).unwrap_or(rps);
         let rl = RateLimiter::new(rps, burst);
-        Client { base, http, rate_limiter: Some(rl) }
+        let private_per_sec: u32 = std::env::var("RATE_LIMIT_PRIVATE_PER_SEC").ok().and_then(|s| s.parse().ok()).unwrap_or(1);
+        let group_per_min: u32 = std::env::var("RATE_LIMIT_GROUP_PER_MIN").ok().and_then(|s| s.parse().ok()).unwrap_or(20);
+        let cl = ChatRateLimiter::new(private_per_sec, group_per_min);
+        Ok(Client {
+            base,
+            file_base,
+            http,
+            rate_limiter: Some(rl),
+            chat_limiter: Some(cl),
+            max_upload_bytes,
+            request_timeout: self.request_timeout,
+            recorder: None,
+            migrations: Default::default(),
+            me: Default::default(),
+        })
     }
 }
 
@@ -101,322 +430,483 @@ impl RateLimiter {
         }
     }
 }
-impl Client {
-    pub async fn send_raw<P: Serialize>(&self, method: &str, params: &P) -> Result<serde_json::Value, BotError> {
-        let url = format!("{}/{}", self.base, method);
-        if let Some(rl) = &self.rate_limiter {
-            rl.acquire().await;
-        }
-        let mut attempt: u32 = 0;
-        let max_attempts: u32 = 5;
-        let mut backoff = Duration::from_millis(500);
+/// Sliding-window limiter keyed by chat id. Telegram allows about one message per
+/// second in a private chat and 20 messages per minute in a group.
+pub struct ChatRateLimiter {
+    sent: Mutex<HashMap<i64, VecDeque<Instant>>>,
+    private: (usize, Duration),
+    group: (usize, Duration),
+}
 
-        loop {
-            attempt += 1;
-            let resp = self.http.post(&url).json(params).send().await?;
-            let status = resp.status();
-            let headers = resp.headers().clone();
-            let text = resp.text().await?;
+impl ChatRateLimiter {
+    pub fn new(private_per_sec: u32, group_per_min: u32) -> std::sync::Arc<ChatRateLimiter> {
+        std::sync::Arc::new(ChatRateLimiter {
+            sent: Mutex::new(HashMap::new()),
+            private: (private_per_sec.max(1) as usize, Duration::from_secs(1)),
+            group: (group_per_min.max(1) as usize, Duration::from_secs(60)),
+        })
+    }
 
-            let api: Result<ApiResponse<serde_json::Value>, serde_json::Error> = serde_json::from_str(&text);
+    // Users and bots have positive ids; groups, supergroups and channels negative.
+    fn limit_for(&self, chat_id: i64) -> (usize, Duration) {
+        if chat_id > 0 { self.private } else { self.group }
+    }
 
-            if status.as_u16() == 429 {
-                let retry_after = headers
-                    .get(reqwest::header::RETRY_AFTER)
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|s| s.parse::<u64>().ok());
-                if let Some(secs) = retry_after {
-                    warn!("received 429, retry after {}s (header)", secs);
-                    sleep(Duration::from_secs(secs)).await;
-                } else {
-                    warn!("received 429, backing off {}ms", backoff.as_millis());
-                    sleep(backoff).await;
-                    backoff = backoff.checked_mul(2).unwrap_or(backoff);
-                }
-                if attempt >= max_attempts { return Err(BotError::Api("too many requests (429)".into())); }
-                continue;
+    pub async fn acquire(&self, chat_id: i64) {
+        let (limit, window) = self.limit_for(chat_id);
+        loop {
+            let mut sent = self.sent.lock().await;
+            let now = Instant::now();
+            if sent.len() > 1024 {
+                sent.retain(|id, q| q.back().map(|t| now.duration_since(*t) < self.limit_for(*id).1).unwrap_or(false));
             }
-
-            if status.is_server_error() {
-                warn!("server error status {} on attempt {}", status, attempt);
-                if attempt >= max_attempts { return Err(BotError::Api(format!("server error: {}", status))); }
-                sleep(backoff).await;
-                backoff = backoff.checked_mul(2).unwrap_or(backoff);
-                continue;
+            let q = sent.entry(chat_id).or_default();
+            while q.front().map(|t| now.duration_since(*t) >= window).unwrap_or(false) {
+                q.pop_front();
             }
+            if q.len() < limit {
+                q.push_back(now);
+                return;
+            }
+            let wait = q.front().map(|t| window.saturating_sub(now.duration_since(*t))).unwrap_or(window);
+            drop(sent);
+            sleep(wait).await;
+        }
+    }
+}
 
-            let api = match api {
-                Ok(a) => a,
-                Err(e) => return Err(BotError::Json(e)),
-            };
+// Methods that post a message into the chat and count towards per-chat limits.
+fn is_message_send(method: &str) -> bool {
+    (method.starts_with("send") && method != "sendChatAction") || method.starts_with("forward") || method.starts_with("copy")
+}
 
-            if api.ok {
-                return Ok(api.result);
-            }
+impl Client {
+    #[allow(dead_code)]
+    pub async fn send_raw<P: Serialize>(&self, method: &str, params: &P) -> Result<serde_json::Value, BotError> {
+        self.send(method, params).await
+    }
 
-            if let Some(desc) = api.description {
-                if let Some(secs) = parse_retry_after_from_description(&desc) {
-                    warn!("telegram responded with retry_after={}s in description", secs);
-                    sleep(Duration::from_secs(secs)).await;
-                    if attempt >= max_attempts { return Err(BotError::Api(desc)); }
+    pub async fn execute<Q: Request>(&self, req: &Q) -> Result<Q::Response, BotError> {
+        let files: Vec<(&'static str, &InputFile)> = req.files().into_iter().filter(|(_, f)| f.is_upload()).collect();
+        if files.is_empty() {
+            return self.send(Q::METHOD, req).await;
+        }
+        let mut fields = Vec::new();
+        if let serde_json::Value::Object(map) = serde_json::to_value(req)? {
+            for (k, v) in map {
+                if files.iter().any(|(name, _)| *name == k) {
                     continue;
                 }
-                return Err(BotError::Api(desc));
-            } else {
-                return Err(BotError::Api("telegram api error".into()));
+                match v {
+                    serde_json::Value::Null => {}
+                    serde_json::Value::String(s) => fields.push((k, s)),
+                    other => fields.push((k, other.to_string())),
+                }
             }
         }
+        self.call_api(Q::METHOD, Payload::Multipart { fields, files }).await
     }
 
-    pub async fn send_message(&self, chat_id: i64, text: &str, reply_markup: Option<serde_json::Value>) -> Result<serde_json::Value, BotError> {
-        
-        let chunks = chunk_message(text, 4000);
-        let total = chunks.len();
-        let mut last_res: Option<serde_json::Value> = None;
-        for (i, chunk) in chunks.into_iter().enumerate() {
-            let mut params = serde_json::json!({"chat_id": chat_id, "text": chunk});
-            
-            if i == 0 {
-                if let Some(rm) = reply_markup.clone() {
-                    params["reply_markup"] = rm;
-                }
-            }
-            let res = self.send_raw("sendMessage", &params).await?;
-            last_res = Some(res);
-            
-            if i + 1 < total {
-                sleep(Duration::from_millis(120)).await;
-            }
-        }
-        Ok(last_res.unwrap_or_else(|| serde_json::Value::Null))
+    pub async fn send_message(&self, chat_id: i64, text: &str, reply_markup: Option<ReplyMarkup>) -> Result<Message, BotError> {
+        self.send_message_with_mode(chat_id, text, reply_markup, None).await
     }
 
-    pub async fn send_message_with_mode(&self, chat_id: i64, text: &str, reply_markup: Option<serde_json::Value>, parse_mode: Option<&str>) -> Result<serde_json::Value, BotError> {
-        let chunks = chunk_message(text, 4000);
+    pub async fn send_message_with_mode(&self, chat_id: i64, text: &str, reply_markup: Option<ReplyMarkup>, parse_mode: Option<ParseMode>) -> Result<Message, BotError> {
+        let mut req = SendMessage::new(chat_id, text);
+        req.reply_markup = reply_markup;
+        req.parse_mode = parse_mode;
+        self.send_message_chunked(req).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn send_message_html(&self, chat_id: i64, text: &str, reply_markup: Option<ReplyMarkup>) -> Result<Message, BotError> {
+        self.send_message_with_mode(chat_id, text, reply_markup, Some(ParseMode::Html)).await
+    }
+
+    /// Renders `text` as HTML and sends it with the matching parse mode.
+    pub async fn send_formatted(&self, chat_id: i64, text: &Text, reply_markup: Option<ReplyMarkup>) -> Result<Message, BotError> {
+        self.send_message_with_mode(chat_id, &text.to_html(), reply_markup, Some(ParseMode::Html)).await
+    }
+
+    /// Sends `req`, splitting its text into several messages when it is too long.
+    /// The reply markup is attached to the first chunk; the last sent message is returned.
+    pub async fn send_message_chunked(&self, req: SendMessage) -> Result<Message, BotError> {
+        let chunks = chunk_message(&req.text, MAX_MESSAGE_LEN, req.parse_mode);
         let total = chunks.len();
-        let mut last_res: Option<serde_json::Value> = None;
+        let mut last_res: Option<Message> = None;
         for (i, chunk) in chunks.into_iter().enumerate() {
-            let mut params = serde_json::json!({"chat_id": chat_id, "text": chunk});
-            if let Some(pm) = parse_mode {
-                params["parse_mode"] = serde_json::Value::String(pm.to_string());
-            }
-            if i == 0 {
-                if let Some(rm) = reply_markup.clone() {
-                    params["reply_markup"] = rm;
-                }
+            let mut params = req.clone();
+            params.text = chunk;
+            if i > 0 {
+                params.reply_markup = None;
             }
-            let res = self.send_raw("sendMessage", &params).await?;
+            let res = self.execute(&params).await?;
             last_res = Some(res);
+
             if i + 1 < total {
                 sleep(Duration::from_millis(120)).await;
             }
         }
-        Ok(last_res.unwrap_or_else(|| serde_json::Value::Null))
+        last_res.ok_or_else(|| BotError::Api("empty message".into()))
     }
 
-    pub async fn send_message_html(&self, chat_id: i64, text: &str, reply_markup: Option<serde_json::Value>) -> Result<serde_json::Value, BotError> {
-        self.send_message_with_mode(chat_id, text, reply_markup, Some("HTML")).await
+    pub async fn send_document(&self, chat_id: i64, document: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendDocument::new(chat_id, document)).await
     }
 
-    pub async fn send_document_path(&self, chat_id: i64, path: &str) -> Result<serde_json::Value, BotError> {
-        let url = format!("{}/sendDocument", self.base);
-        let md = tokio::fs::metadata(path).await?;
-        if md.len() > max_upload_bytes() {
-            return Err(BotError::Api(format!("file too large: {} bytes (max {} bytes)", md.len(), max_upload_bytes())));
-        }
-        let file = fs::File::open(path).await?;
-        let filename = Path::new(path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("file")
-            .to_string();
-        let stream = ReaderStream::new(file);
-        let body = reqwest::Body::wrap_stream(stream);
-        let part = Part::stream(body).file_name(filename);
-        let form = Form::new().text("chat_id", chat_id.to_string()).part("document", part);
-        let resp = self.http.post(&url).multipart(form).send().await?;
-        let text = resp.text().await?;
-        let api: ApiResponse<serde_json::Value> = serde_json::from_str(&text)?;
-        if api.ok { Ok(api.result) } else { Err(BotError::Api(api.description.unwrap_or_else(|| "telegram api error".into()))) }
+    #[allow(dead_code)]
+    pub async fn send_photo(&self, chat_id: i64, photo: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendPhoto::new(chat_id, photo)).await
     }
 
     #[allow(dead_code)]
-    pub async fn send_photo_path(&self, chat_id: i64, path: &str) -> Result<serde_json::Value, BotError> {
-        let url = format!("{}/sendPhoto", self.base);
-        let md = tokio::fs::metadata(path).await?;
-        if md.len() > max_upload_bytes() {
-            return Err(BotError::Api(format!("file too large: {} bytes (max {} bytes)", md.len(), max_upload_bytes())));
-        }
-        let file = fs::File::open(path).await?;
-        let filename = Path::new(path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("photo")
-            .to_string();
-        let stream = ReaderStream::new(file);
-        let body = reqwest::Body::wrap_stream(stream);
-        let part = Part::stream(body).file_name(filename);
-        let form = Form::new().text("chat_id", chat_id.to_string()).part("photo", part);
-        let resp = self.http.post(&url).multipart(form).send().await?;
-        let text = resp.text().await?;
-        let api: ApiResponse<serde_json::Value> = serde_json::from_str(&text)?;
-        if api.ok { Ok(api.result) } else { Err(BotError::Api(api.description.unwrap_or_else(|| "telegram api error".into()))) }
+    pub async fn send_audio(&self, chat_id: i64, audio: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendAudio::new(chat_id, audio)).await
     }
 
     #[allow(dead_code)]
-    pub async fn send_audio_path(&self, chat_id: i64, path: &str) -> Result<serde_json::Value, BotError> {
-        let url = format!("{}/sendAudio", self.base);
-        let md = tokio::fs::metadata(path).await?;
-        if md.len() > max_upload_bytes() {
-            return Err(BotError::Api(format!("file too large: {} bytes (max {} bytes)", md.len(), max_upload_bytes())));
-        }
-        let file = fs::File::open(path).await?;
-        let filename = Path::new(path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("audio")
-            .to_string();
-        let stream = ReaderStream::new(file);
-        let body = reqwest::Body::wrap_stream(stream);
-        let part = Part::stream(body).file_name(filename);
-        let form = Form::new().text("chat_id", chat_id.to_string()).part("audio", part);
-        let resp = self.http.post(&url).multipart(form).send().await?;
-        let text = resp.text().await?;
-        let api: ApiResponse<serde_json::Value> = serde_json::from_str(&text)?;
-        if api.ok { Ok(api.result) } else { Err(BotError::Api(api.description.unwrap_or_else(|| "telegram api error".into()))) }
+    pub async fn send_voice(&self, chat_id: i64, voice: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendVoice::new(chat_id, voice)).await
     }
 
     #[allow(dead_code)]
-    pub async fn send_voice_path(&self, chat_id: i64, path: &str) -> Result<serde_json::Value, BotError> {
-        let url = format!("{}/sendVoice", self.base);
-        let md = tokio::fs::metadata(path).await?;
-        if md.len() > max_upload_bytes() {
-            return Err(BotError::Api(format!("file too large: {} bytes (max {} bytes)", md.len(), max_upload_bytes())));
-        }
-        let file = fs::File::open(path).await?;
-        let filename = Path::new(path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("voice")
-            .to_string();
-        let stream = ReaderStream::new(file);
-        let body = reqwest::Body::wrap_stream(stream);
-        let part = Part::stream(body).file_name(filename);
-        let form = Form::new().text("chat_id", chat_id.to_string()).part("voice", part);
-        let resp = self.http.post(&url).multipart(form).send().await?;
-        let text = resp.text().await?;
-        let api: ApiResponse<serde_json::Value> = serde_json::from_str(&text)?;
-        if api.ok { Ok(api.result) } else { Err(BotError::Api(api.description.unwrap_or_else(|| "telegram api error".into()))) }
+    pub async fn send_video(&self, chat_id: i64, video: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendVideo::new(chat_id, video)).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn send_animation(&self, chat_id: i64, animation: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendAnimation::new(chat_id, animation)).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn send_video_note(&self, chat_id: i64, video_note: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendVideoNote::new(chat_id, video_note)).await
     }
 
     #[allow(dead_code)]
-    pub async fn send_sticker_path(&self, chat_id: i64, path: &str) -> Result<serde_json::Value, BotError> {
-        let url = format!("{}/sendSticker", self.base);
-        let md = tokio::fs::metadata(path).await?;
-        if md.len() > max_upload_bytes() {
-            return Err(BotError::Api(format!("file too large: {} bytes (max {} bytes)", md.len(), max_upload_bytes())));
+    pub async fn send_sticker(&self, chat_id: i64, sticker: InputFile) -> Result<Message, BotError> {
+        self.execute(&SendSticker::new(chat_id, sticker)).await
+    }
+
+    /// Sends a regular poll or, built with `SendPoll::quiz`, a quiz.
+    pub async fn send_poll(&self, poll: SendPoll) -> Result<Message, BotError> {
+        self.execute(&poll).await
+    }
+
+    /// Closes a poll the bot sent and returns its final results.
+    pub async fn stop_poll(&self, chat_id: i64, message_id: i64) -> Result<Poll, BotError> {
+        self.execute(&StopPoll { chat_id, message_id, reply_markup: None }).await
+    }
+
+    async fn file_part(&self, file: &InputFile, fallback_name: &str) -> Result<Part, BotError> {
+        let too_large = |len: u64| BotError::Api(format!("file too large: {} bytes (max {} bytes)", len, self.max_upload_bytes));
+        match file {
+            InputFile::Path(path) => {
+                let md = fs::metadata(path).await?;
+                if md.len() > self.max_upload_bytes {
+                    return Err(too_large(md.len()));
+                }
+                let f = fs::File::open(path).await?;
+                let body = reqwest::Body::wrap_stream(ReaderStream::new(f));
+                Ok(Part::stream_with_length(body, md.len()).file_name(file.filename(fallback_name)))
+            }
+            InputFile::Bytes { data, .. } => {
+                if data.len() as u64 > self.max_upload_bytes {
+                    return Err(too_large(data.len() as u64));
+                }
+                Ok(Part::bytes(data.clone()).file_name(file.filename(fallback_name)))
+            }
+            InputFile::FileId(_) | InputFile::Url(_) => Err(BotError::Api("file id or url is not an upload".into())),
         }
-        let file = fs::File::open(path).await?;
-        let filename = Path::new(path)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("sticker")
-            .to_string();
-        let stream = ReaderStream::new(file);
-        let body = reqwest::Body::wrap_stream(stream);
-        let part = Part::stream(body).file_name(filename);
-        let form = Form::new().text("chat_id", chat_id.to_string()).part("sticker", part);
-        let resp = self.http.post(&url).multipart(form).send().await?;
-        let text = resp.text().await?;
-        let api: ApiResponse<serde_json::Value> = serde_json::from_str(&text)?;
-        if api.ok { Ok(api.result) } else { Err(BotError::Api(api.description.unwrap_or_else(|| "telegram api error".into()))) }
     }
 
-    pub async fn send<R: DeserializeOwned, P: Serialize>(&self, method: &str, params: &P) -> Result<R, BotError> {
+    /// The HTTP timeout for one request. A getUpdates call is held open by the
+    /// server for its long-poll `timeout`, which is added on top; without a
+    /// configured request timeout it still gets `POLL_TIMEOUT_MARGIN` of slack.
+    fn timeout_for(&self, payload: &Payload<'_>, method: &str) -> Option<Duration> {
+        if method != GetUpdates::METHOD {
+            return self.request_timeout;
+        }
+        let long_poll = match payload {
+            Payload::Json(v) => v.get("timeout").and_then(|t| t.as_u64()).unwrap_or(0),
+            Payload::Multipart { .. } => 0,
+        };
+        Some(Duration::from_secs(long_poll) + self.request_timeout.unwrap_or(POLL_TIMEOUT_MARGIN))
+    }
+
+    async fn build_request(&self, url: &str, method: &str, payload: &Payload<'_>) -> Result<reqwest::RequestBuilder, BotError> {
+        let req = self.build_body(url, payload).await?;
+        Ok(match self.timeout_for(payload, method) {
+            Some(t) => req.timeout(t),
+            None => req,
+        })
+    }
+
+    async fn build_body(&self, url: &str, payload: &Payload<'_>) -> Result<reqwest::RequestBuilder, BotError> {
+        match payload {
+            Payload::Json(body) => Ok(self.http.post(url).json(body)),
+            Payload::Multipart { fields, files } => {
+                let mut form = Form::new();
+                for (k, v) in fields {
+                    form = form.text(k.clone(), v.clone());
+                }
+                // Parts are rebuilt (and files reopened) on every attempt, since a
+                // streamed body cannot be replayed once it has been sent.
+                for (name, file) in files {
+                    form = form.part(*name, self.file_part(file, name).await?);
+                }
+                Ok(self.http.post(url).multipart(form))
+            }
+        }
+    }
+
+    /// The single outgoing request pipeline: rate limiting, retries with backoff on
+    /// 429/5xx and decoding of the Bot API response envelope.
+    async fn call_api<R: DeserializeOwned>(&self, method: &str, mut payload: Payload<'_>) -> Result<R, BotError> {
         let url = format!("{}/{}", self.base, method);
+        if let Some(to) = payload.chat_id().and_then(|id| self.resolve_chat_id(id)) {
+            payload.set_chat_id(to);
+        }
+        let mut migrated = false;
         let mut attempt: u32 = 0;
         let max_attempts: u32 = 5;
         let mut backoff = Duration::from_millis(500);
 
         loop {
             attempt += 1;
-            let resp = self.http.post(&url).json(params).send().await?;
+            if let (Some(cl), Some(chat_id)) = (&self.chat_limiter, payload.chat_id()) {
+                if is_message_send(method) {
+                    let started = Instant::now();
+                    cl.acquire(chat_id).await;
+                    metrics::get().rate_limit_wait("chat", started.elapsed());
+                }
+            }
+            // A long poll is not an outgoing message, so it does not spend rate limiter tokens.
+            if method != GetUpdates::METHOD {
+                if let Some(rl) = &self.rate_limiter {
+                    let started = Instant::now();
+                    rl.acquire().await;
+                    metrics::get().rate_limit_wait("global", started.elapsed());
+                }
+            }
+            let resp = match self.build_request(&url, method, &payload).await?.send().await {
+                Ok(r) => r,
+                Err(e) => {
+                    metrics::get().api_response(method, None);
+                    return Err(e.into());
+                }
+            };
             let status = resp.status();
+            metrics::get().api_response(method, Some(status.as_u16()));
             let headers = resp.headers().clone();
             let text = resp.text().await?;
+            if let Some(rec) = &self.recorder {
+                rec.record_exchange(method, payload.to_value(), status.as_u16(), &text);
+            }
+            let api: Option<ApiResponse<serde_json::Value>> = serde_json::from_str(&text).ok();
 
-            if status.as_u16() == 429 {
-                let retry_after = headers
-                    .get(reqwest::header::RETRY_AFTER)
-                    .and_then(|v| v.to_str().ok())
-                    .and_then(|s| s.parse::<u64>().ok());
+            let api = match api {
+                Some(a) if a.ok => return Ok(serde_json::from_value(a.result.unwrap_or(serde_json::Value::Null))?),
+                other => other,
+            };
+
+            let retry_after = api
+                .as_ref()
+                .and_then(|a| a.parameters.as_ref())
+                .and_then(|p| p.retry_after)
+                .or_else(|| {
+                    headers
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|s| s.parse::<u64>().ok())
+                });
+
+            if status.as_u16() == 429 || retry_after.is_some() {
+                if attempt >= max_attempts {
+                    return Err(match api {
+                        Some(api) => BotError::telegram(api, status.as_u16()),
+                        None => BotError::Api("too many requests (429)".into()),
+                    });
+                }
+                metrics::get().api_retry(method, "too_many_requests");
                 if let Some(secs) = retry_after {
-                    warn!("received 429, retry after {}s (header)", secs);
+                    warn!("received 429, retry after {}s", secs);
                     sleep(Duration::from_secs(secs)).await;
                 } else {
                     warn!("received 429, backing off {}ms", backoff.as_millis());
                     sleep(backoff).await;
                     backoff = backoff.checked_mul(2).unwrap_or(backoff);
                 }
-                if attempt >= max_attempts { return Err(BotError::Api("too many requests (429)".into())); }
                 continue;
             }
 
             if status.is_server_error() {
                 warn!("server error status {} on attempt {}", status, attempt);
-                if attempt >= max_attempts { return Err(BotError::Api(format!("server error: {}", status))); }
+                if attempt >= max_attempts {
+                    return Err(match api {
+                        Some(api) => BotError::telegram(api, status.as_u16()),
+                        None => BotError::Api(format!("server error: {}", status)),
+                    });
+                }
+                metrics::get().api_retry(method, "server_error");
                 sleep(backoff).await;
                 backoff = backoff.checked_mul(2).unwrap_or(backoff);
                 continue;
             }
 
-            let api: ApiResponse<R> = serde_json::from_str(&text)?;
-            if api.ok { return Ok(api.result); }
-            if let Some(desc) = api.description {
-                if let Some(secs) = parse_retry_after_from_description(&desc) {
-                    warn!("telegram responded with retry_after={}s in description", secs);
-                    sleep(Duration::from_secs(secs)).await;
-                    if attempt >= max_attempts { return Err(BotError::Api(desc)); }
-                    continue;
-                }
-                return Err(BotError::Api(desc));
+            let migrate_to = api.as_ref().and_then(|a| a.parameters.as_ref()).and_then(|p| p.migrate_to_chat_id);
+            if let (Some(to), Some(from), false) = (migrate_to, payload.chat_id(), migrated) {
+                warn!("chat {} was migrated to supergroup {}, resending", from, to);
+                self.record_chat_migration(from, to);
+                payload.set_chat_id(to);
+                migrated = true;
+                continue;
             }
-            return Err(BotError::Api("telegram api error".into()));
+
+            return Err(match api {
+                Some(api) => BotError::telegram(api, status.as_u16()),
+                None => {
+                    let snippet: String = text.chars().take(200).collect();
+                    BotError::Api(format!("unexpected response from {} (status {}): {}", method, status, snippet))
+                }
+            });
         }
     }
 
-    pub async fn get_updates(&self, offset: i64, timeout: u64) -> Result<Vec<Update>, BotError> {
-        let params = serde_json::json!({"offset": offset, "timeout": timeout});
-        let updates: Vec<Update> = self.send("getUpdates", &params).await?;
-        Ok(updates)
+    pub async fn send<R: DeserializeOwned, P: Serialize>(&self, method: &str, params: &P) -> Result<R, BotError> {
+        self.call_api(method, Payload::Json(serde_json::to_value(params)?)).await
+    }
+
+    /// Long-polls for updates. An empty `allowed_updates` asks for Telegram's
+    /// default set, which leaves out chat_member and message_reaction.
+    pub async fn get_updates(&self, offset: i64, timeout: u64, allowed_updates: &[UpdateKind]) -> Result<Vec<Update>, BotError> {
+        self.execute(&GetUpdates::new().offset(offset).timeout(timeout).allowed_updates(allowed_updates.to_vec())).await
+    }
+
+    pub async fn set_webhook(&self, url: &str, secret_token: Option<&str>, drop_pending_updates: bool, allowed_updates: Option<Vec<UpdateKind>>) -> Result<bool, BotError> {
+        let mut req = SetWebhook::new(url).drop_pending_updates(drop_pending_updates);
+        req.secret_token = secret_token.map(|s| s.to_string());
+        req.allowed_updates = allowed_updates;
+        self.execute(&req).await
+    }
+
+    pub async fn delete_webhook(&self, drop_pending_updates: bool) -> Result<bool, BotError> {
+        self.execute(&DeleteWebhook { drop_pending_updates: Some(drop_pending_updates) }).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn get_webhook_info(&self) -> Result<WebhookInfo, BotError> {
+        self.execute(&GetWebhookInfo::default()).await
+    }
+
+    pub async fn set_my_commands(&self, commands: Vec<BotCommand>, scope: Option<BotCommandScope>, language_code: Option<&str>) -> Result<bool, BotError> {
+        self.execute(&SetMyCommands { commands, scope, language_code: language_code.map(|l| l.to_string()) }).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn delete_my_commands(&self, scope: Option<BotCommandScope>, language_code: Option<&str>) -> Result<bool, BotError> {
+        self.execute(&DeleteMyCommands { scope, language_code: language_code.map(|l| l.to_string()) }).await
     }
 
-    pub async fn get_chat(&self, chat_id: i64) -> Result<serde_json::Value, BotError> {
-        let params = serde_json::json!({"chat_id": chat_id});
-        let v: serde_json::Value = self.send("getChat", &params).await?;
-        Ok(v)
+    pub async fn get_me(&self) -> Result<User, BotError> {
+        self.execute(&GetMe::default()).await
+    }
+
+    pub async fn get_chat(&self, chat_id: i64) -> Result<ChatFullInfo, BotError> {
+        self.execute(&GetChat { chat_id }).await
     }
 
     pub async fn get_user_profile_photos(&self, user_id: i64) -> Result<UserProfilePhotos, BotError> {
-        let params = serde_json::json!({"user_id": user_id});
-        let uph: UserProfilePhotos = self.send("getUserProfilePhotos", &params).await?;
-        Ok(uph)
+        self.execute(&GetUserProfilePhotos::new(user_id)).await
     }
 
     pub async fn get_file(&self, file_id: &str) -> Result<File, BotError> {
-        let params = serde_json::json!({"file_id": file_id});
-        let f: File = self.send("getFile", &params).await?;
-        Ok(f)
+        self.execute(&GetFile { file_id: file_id.to_string() }).await
     }
 
     pub async fn download_file_bytes(&self, file_path: &str) -> Result<Vec<u8>, BotError> {
-        let file_base = self.base.replacen("/bot", "/file/bot", 1);
-        let url = format!("{}/{}", file_base, file_path);
-        let resp = self.http.get(&url).send().await?;
+        // A telegram-bot-api server running with --local returns absolute paths
+        // on its own filesystem instead of a downloadable relative path.
+        if Path::new(file_path).is_absolute() {
+            return Ok(fs::read(file_path).await?);
+        }
+        let url = format!("{}/{}", self.file_base, file_path);
+        let resp = self.http.get(&url).send().await?.error_for_status()?;
         let bytes = resp.bytes().await?;
         Ok(bytes.to_vec())
     }
 
+    pub async fn edit_message_text(&self, chat_id: i64, message_id: i64, text: &str, reply_markup: Option<InlineKeyboardMarkup>) -> Result<EditResult, BotError> {
+        let mut req = EditMessageText::new(chat_id, message_id, text);
+        req.reply_markup = reply_markup;
+        self.execute(&req).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn edit_inline_message_text(&self, inline_message_id: &str, text: &str, reply_markup: Option<InlineKeyboardMarkup>) -> Result<EditResult, BotError> {
+        let mut req = EditMessageText::inline(inline_message_id, text);
+        req.reply_markup = reply_markup;
+        self.execute(&req).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn edit_message_caption(&self, chat_id: i64, message_id: i64, caption: Option<&str>, reply_markup: Option<InlineKeyboardMarkup>) -> Result<EditResult, BotError> {
+        let mut req = EditMessageCaption::new(chat_id, message_id);
+        req.caption = caption.map(|c| c.to_string());
+        req.reply_markup = reply_markup;
+        self.execute(&req).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn edit_inline_message_caption(&self, inline_message_id: &str, caption: Option<&str>, reply_markup: Option<InlineKeyboardMarkup>) -> Result<EditResult, BotError> {
+        let mut req = EditMessageCaption::inline(inline_message_id);
+        req.caption = caption.map(|c| c.to_string());
+        req.reply_markup = reply_markup;
+        self.execute(&req).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn edit_message_reply_markup(&self, chat_id: i64, message_id: i64, reply_markup: Option<InlineKeyboardMarkup>) -> Result<EditResult, BotError> {
+        let mut req = EditMessageReplyMarkup::new(chat_id, message_id);
+        req.reply_markup = reply_markup;
+        self.execute(&req).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn edit_inline_message_reply_markup(&self, inline_message_id: &str, reply_markup: Option<InlineKeyboardMarkup>) -> Result<EditResult, BotError> {
+        let mut req = EditMessageReplyMarkup::inline(inline_message_id);
+        req.reply_markup = reply_markup;
+        self.execute(&req).await
+    }
+
+    pub async fn delete_message(&self, chat_id: i64, message_id: i64) -> Result<bool, BotError> {
+        self.execute(&DeleteMessage { chat_id, message_id }).await
+    }
+
+    #[allow(dead_code)]
+    pub async fn delete_messages(&self, chat_id: i64, message_ids: Vec<i64>) -> Result<bool, BotError> {
+        self.execute(&DeleteMessages { chat_id, message_ids }).await
+    }
+
+    /// Replaces the text and keyboard of the message a callback button was pressed on,
+    /// whether it is a regular or an inline message. Pressing a button that would not
+    /// change anything is not treated as an error.
+    pub async fn edit_callback_message(&self, cb: &CallbackQuery, text: &str, reply_markup: Option<InlineKeyboardMarkup>) -> Result<(), BotError> {
+        let res = if let Some(id) = &cb.inline_message_id {
+            self.edit_inline_message_text(id, text, reply_markup).await
+        } else if let Some(msg) = &cb.message {
+            self.edit_message_text(msg.chat.id, msg.message_id, text, reply_markup).await
+        } else {
+            return Err(BotError::Api("callback query has no message to edit".into()));
+        };
+        match res {
+            Ok(_) => Ok(()),
+            Err(e) if e.is_message_not_modified() => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn answer_callback_query(
         &self,
         callback_query_id: &str,
@@ -424,82 +914,230 @@ impl Client {
         show_alert: Option<bool>,
         url: Option<&str>,
         cache_time: Option<i32>,
-    ) -> Result<serde_json::Value, BotError> {
-        let mut params = serde_json::json!({"callback_query_id": callback_query_id});
-        if let Some(t) = text {
-            params["text"] = serde_json::Value::String(t.to_string());
-        }
-        if let Some(sa) = show_alert {
-            params["show_alert"] = serde_json::Value::Bool(sa);
-        }
-        if let Some(u) = url {
-            params["url"] = serde_json::Value::String(u.to_string());
-        }
-        if let Some(ct) = cache_time {
-            params["cache_time"] = serde_json::Value::Number(serde_json::Number::from(ct));
-        }
-        self.send_raw("answerCallbackQuery", &params).await
+    ) -> Result<bool, BotError> {
+        let req = AnswerCallbackQuery {
+            callback_query_id: callback_query_id.to_string(),
+            text: text.map(|t| t.to_string()),
+            show_alert,
+            url: url.map(|u| u.to_string()),
+            cache_time,
+        };
+        self.execute(&req).await
     }
-}
 
-fn parse_retry_after_from_description(desc: &str) -> Option<u64> {
-    let s = desc.to_lowercase();
-    if let Some(pos) = s.find("retry after") {
-        let tail = &s[pos + "retry after".len()..];
-        for tok in tail.split(|c: char| !c.is_digit(10)) {
-            if tok.is_empty() { continue; }
-            if let Ok(n) = tok.parse::<u64>() {
-                return Some(n);
-            }
-        }
+    /// Answers an inline query; `AnswerInlineQuery::page` builds one page of a longer result list.
+    pub async fn answer_inline_query(&self, answer: AnswerInlineQuery) -> Result<bool, BotError> {
+        self.execute(&answer).await
+    }
+
+    pub async fn send_invoice(&self, chat_id: i64, invoice: InvoiceParams) -> Result<Message, BotError> {
+        self.execute(&SendInvoice::new(chat_id, invoice)).await
+    }
+
+    /// A t.me link that opens the invoice, for sharing outside a chat with the bot.
+    #[allow(dead_code)]
+    pub async fn create_invoice_link(&self, invoice: InvoiceParams) -> Result<String, BotError> {
+        self.execute(&CreateInvoiceLink::new(invoice)).await
+    }
+
+    /// `Ok` lists the shipping options for the address; `Err` is shown to the
+    /// user when it cannot be delivered to.
+    pub async fn answer_shipping_query(&self, shipping_query_id: &str, result: Result<Vec<ShippingOption>, &str>) -> Result<bool, BotError> {
+        let req = AnswerShippingQuery {
+            shipping_query_id: shipping_query_id.to_string(),
+            ok: result.is_ok(),
+            error_message: result.as_ref().err().map(|e| e.to_string()),
+            shipping_options: result.ok(),
+        };
+        self.execute(&req).await
+    }
+
+    /// Confirms the order, or turns it down with an error shown to the user.
+    /// Telegram cancels the payment unless this arrives within 10 seconds.
+    pub async fn answer_pre_checkout_query(&self, pre_checkout_query_id: &str, result: Result<(), &str>) -> Result<bool, BotError> {
+        let req = AnswerPreCheckoutQuery {
+            pre_checkout_query_id: pre_checkout_query_id.to_string(),
+            ok: result.is_ok(),
+            error_message: result.err().map(|e| e.to_string()),
+        };
+        self.execute(&req).await
+    }
+
+    pub async fn refund_star_payment(&self, user_id: i64, telegram_payment_charge_id: &str) -> Result<bool, BotError> {
+        self.execute(&RefundStarPayment { user_id, telegram_payment_charge_id: telegram_payment_charge_id.to_string() }).await
     }
-    None
 }
 
-fn chunk_message(s: &str, max_len: usize) -> Vec<String> {
-    use unicode_segmentation::UnicodeSegmentation;
-    
-    let gcount = s.graphemes(true).count();
-    if gcount <= max_len { return vec![s.to_string()]; }
-
-    let mut parts: Vec<String> = Vec::new();
-
-   
-    for para in s.split('\n') {
-        let mut cur_graphemes: Vec<&str> = Vec::new();
-        let mut cur_count = 0usize;
-
-        for g in para.graphemes(true) {
-            let g_len = 1; 
-            if cur_count + g_len > max_len {
-               
-                if !cur_graphemes.is_empty() {
-                    parts.push(cur_graphemes.join(""));
-                    cur_graphemes.clear();
-                    cur_count = 0;
-                }
-            }
-            cur_graphemes.push(g);
-            cur_count += g_len;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockBotApi, Reply, MOCK_BOT_USERNAME};
+    use crate::types::MessageKind;
+    use serde_json::json;
 
-        if !cur_graphemes.is_empty() {
-            parts.push(cur_graphemes.join(""));
-        }
+    #[tokio::test]
+    async fn send_message_posts_json_params() {
+        let api = MockBotApi::start().await;
+        let msg = api.client().send_message(42, "hello", None).await.unwrap();
+        assert_eq!(msg.chat.id, 42);
+        assert_eq!(msg.text.as_deref(), Some("hello"));
+
+        let calls = api.calls_to("sendMessage");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].i64("chat_id"), Some(42));
+        assert_eq!(calls[0].str("text"), Some("hello"));
     }
 
-   
-    let mut merged: Vec<String> = Vec::new();
-    for p in parts {
-        if let Some(last) = merged.last_mut() {
-            let combined = format!("{}\n{}", last, p);
-            if combined.graphemes(true).count() <= max_len {
-                *last = combined;
-                continue;
-            }
-        }
-        merged.push(p);
+    #[tokio::test]
+    async fn retries_after_429_with_retry_after() {
+        let api = MockBotApi::start().await;
+        api.enqueue("sendMessage", Reply::retry_after(1));
+        let started = Instant::now();
+        api.client().send_message(42, "hi", None).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(api.calls_to("sendMessage").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_after_429_with_header_only() {
+        let api = MockBotApi::start().await;
+        api.enqueue("sendMessage", Reply::status(429).with_retry_after_header(1));
+        api.client().send_message(42, "hi", None).await.unwrap();
+        assert_eq!(api.calls_to("sendMessage").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_with_backoff() {
+        let api = MockBotApi::start().await;
+        api.enqueue("sendMessage", Reply::status(502));
+        api.enqueue("sendMessage", Reply::error(500, "Internal Server Error"));
+        api.client().send_message(42, "hi", None).await.unwrap();
+        assert_eq!(api.calls_to("sendMessage").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn telegram_errors_are_decoded_and_not_retried() {
+        let api = MockBotApi::start().await;
+        api.enqueue("sendMessage", Reply::error(403, "Forbidden: bot was blocked by the user"));
+        let err = api.client().send_message(42, "hi", None).await.unwrap_err();
+        assert_eq!(err.error_code(), Some(403));
+        assert!(err.is_blocked_by_user());
+        assert_eq!(api.calls_to("sendMessage").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn resends_to_migrated_chat() {
+        let api = MockBotApi::start().await;
+        api.enqueue("sendMessage", Reply::migrate_to(-1001234));
+        let client = api.client();
+        let msg = client.send_message(-55, "hi", None).await.unwrap();
+        assert_eq!(msg.chat.id, -1001234);
+        assert_eq!(client.chat_migrations(), vec![(-55, -1001234)]);
+
+        client.send_message(-55, "again", None).await.unwrap();
+        let ids: Vec<_> = api.calls_to("sendMessage").iter().map(|c| c.i64("chat_id")).collect();
+        assert_eq!(ids, vec![Some(-55), Some(-1001234), Some(-1001234)]);
     }
 
-    merged
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn uploads_document_and_downloads_it_back() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        client.send_document(42, InputFile::bytes("notes.txt", b"file body".to_vec())).await.unwrap();
+
+        let call = &api.calls_to("sendDocument")[0];
+        assert_eq!(call.i64("chat_id"), Some(42));
+        assert_eq!(call.files.len(), 1);
+        assert_eq!(call.files[0].field, "document");
+        assert_eq!(call.files[0].filename.as_deref(), Some("notes.txt"));
+        assert_eq!(call.files[0].data, b"file body");
+
+        api.add_file("doc1", b"stored".to_vec());
+        let file = client.get_file("doc1").await.unwrap();
+        let bytes = client.download_file_bytes(file.file_path.as_deref().unwrap()).await.unwrap();
+        assert_eq!(bytes, b"stored");
+    }
+
+    #[tokio::test]
+    async fn sends_each_media_type() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let msg = client.send_photo(42, InputFile::bytes("cat.jpg", b"jpeg".to_vec())).await.unwrap();
+        let call = &api.calls_to("sendPhoto")[0];
+        assert_eq!(call.files[0].field, "photo");
+        assert_eq!(call.files[0].data, b"jpeg");
+        assert_eq!(msg.kind(), MessageKind::Photo);
+        let largest = msg.largest_photo().unwrap();
+        assert_eq!((largest.width, largest.file_size), (Some(1280), Some(4)));
+        assert_eq!(msg.file_id(), Some(largest.file_id.as_str()));
+
+        let sent = client.send_photo(42, InputFile::file_id(largest.file_id.clone())).await.unwrap();
+        assert_eq!(sent.largest_photo().map(|p| p.file_id.as_str()), Some(largest.file_id.as_str()));
+
+        assert_eq!(client.send_audio(42, InputFile::file_id("a")).await.unwrap().kind(), MessageKind::Audio);
+        assert_eq!(client.send_voice(42, InputFile::file_id("v")).await.unwrap().kind(), MessageKind::Voice);
+        assert_eq!(client.send_video(42, InputFile::file_id("vd")).await.unwrap().kind(), MessageKind::Video);
+        assert_eq!(client.send_animation(42, InputFile::file_id("an")).await.unwrap().kind(), MessageKind::Animation);
+        assert_eq!(client.send_video_note(42, InputFile::file_id("vn")).await.unwrap().kind(), MessageKind::VideoNote);
+        assert_eq!(client.send_sticker(42, InputFile::file_id("st")).await.unwrap().kind(), MessageKind::Sticker);
+        assert_eq!(client.send_document(42, InputFile::file_id("d")).await.unwrap().kind(), MessageKind::Document);
+    }
+
+    #[tokio::test]
+    async fn get_updates_confirms_with_offset() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let first = api.push_message(42, 42, "one");
+        api.push_update(json!({ "message": { "message_id": 9, "chat": { "id": 42 }, "text": "two" } }));
+
+        let updates = client.get_updates(0, 0, &[]).await.unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].update_id, first);
+
+        let updates = client.get_updates(first + 1, 0, &[]).await.unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].message.as_ref().and_then(|m| m.text.as_deref()), Some("two"));
+    }
+
+    #[tokio::test]
+    async fn long_poll_outlasts_request_timeout() {
+        let api = MockBotApi::start().await;
+        let mut client = api.client();
+        client.request_timeout = Some(Duration::from_millis(200));
+        let started = Instant::now();
+        let updates = client.get_updates(0, 1, &[]).await.unwrap();
+        assert!(updates.is_empty());
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn routes_requests_through_proxy() {
+        let api = MockBotApi::start().await;
+        let mut client = Client::builder(crate::testing::MOCK_TOKEN)
+            .api_url("http://api.telegram.invalid")
+            .proxy(api.url())
+            .proxy_auth("user", "secret")
+            .connect_timeout(Duration::from_secs(5))
+            .user_agent("bot-test")
+            .try_build()
+            .unwrap();
+        client.rate_limiter = None;
+        let me = client.get_me().await.unwrap();
+        assert_eq!(me.username.as_deref(), Some(MOCK_BOT_USERNAME));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_proxy_url() {
+        assert!(Client::builder("1:x").proxy("not a url").try_build().is_err());
+    }
+
+    #[tokio::test]
+    async fn get_me_sets_identity() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let me = client.get_me().await.unwrap();
+        client.set_me(me);
+        assert_eq!(client.bot_username(), Some(MOCK_BOT_USERNAME));
+        assert_eq!(client.deep_link("ref").as_deref(), Some("https://t.me/mock_bot?start=ref"));
+    }
+}
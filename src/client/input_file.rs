@@ -0,0 +1,58 @@
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Serializer};
+
+/// A file passed to a send-media method. Paths and in-memory bytes are uploaded
+/// as multipart parts; file ids and URLs are sent as plain strings.
+#[derive(Debug, Clone)]
+pub enum InputFile {
+    Path(PathBuf),
+    Bytes { data: Vec<u8>, filename: String },
+    FileId(String),
+    Url(String),
+}
+
+#[allow(dead_code)]
+impl InputFile {
+    pub fn path(path: impl Into<PathBuf>) -> Self {
+        InputFile::Path(path.into())
+    }
+
+    pub fn bytes(filename: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        InputFile::Bytes { data: data.into(), filename: filename.into() }
+    }
+
+    pub fn file_id(id: impl Into<String>) -> Self {
+        InputFile::FileId(id.into())
+    }
+
+    pub fn url(url: impl Into<String>) -> Self {
+        InputFile::Url(url.into())
+    }
+
+    /// Whether the file has to be sent as a multipart upload.
+    pub fn is_upload(&self) -> bool {
+        matches!(self, InputFile::Path(_) | InputFile::Bytes { .. })
+    }
+
+    pub fn filename(&self, fallback: &str) -> String {
+        match self {
+            InputFile::Path(p) => Path::new(p)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or(fallback)
+                .to_string(),
+            InputFile::Bytes { filename, .. } => filename.clone(),
+            _ => fallback.to_string(),
+        }
+    }
+}
+
+impl Serialize for InputFile {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            InputFile::FileId(s) | InputFile::Url(s) => serializer.serialize_str(s),
+            // Uploads are replaced by multipart parts when the request is built.
+            InputFile::Path(_) | InputFile::Bytes { .. } => serializer.serialize_none(),
+        }
+    }
+}
@@ -0,0 +1,762 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use super::InputFile;
+use crate::types::{
+    BotCommand, BotCommandScope, ChatFullInfo, EditResult, File, InlineKeyboardMarkup, InlineQuery, InlineQueryResult, InlineQueryResultsButton, InputPollOption, LabeledPrice, LinkPreviewOptions, Message, ParseMode, Poll, PollKind, ReplyMarkup, ShippingOption, Update,
+    UpdateKind, User, UserProfilePhotos, WebhookInfo,
+};
+
+/// A Bot API method: the parameters serialize to the request body, `METHOD` is the
+/// endpoint name and `Response` is what Telegram puts in `result`.
+pub trait Request: Serialize {
+    type Response: DeserializeOwned;
+    const METHOD: &'static str;
+
+    /// File parameters by field name. Requests carrying an upload are sent as multipart.
+    fn files(&self) -> Vec<(&'static str, &InputFile)> {
+        Vec::new()
+    }
+}
+
+macro_rules! setters {
+    ($($name:ident: $ty:ty),* $(,)?) => {
+        $(
+            pub fn $name(mut self, $name: $ty) -> Self {
+                self.$name = Some($name.into());
+                self
+            }
+        )*
+    };
+}
+
+macro_rules! request {
+    ($ty:ty, $method:literal, $resp:ty) => {
+        impl Request for $ty {
+            type Response = $resp;
+            const METHOD: &'static str = $method;
+        }
+    };
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GetUpdates {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_updates: Option<Vec<UpdateKind>>,
+}
+request!(GetUpdates, "getUpdates", Vec<Update>);
+
+#[allow(dead_code)]
+impl GetUpdates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    setters!(offset: i64, limit: u32, timeout: u64, allowed_updates: Vec<UpdateKind>);
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SendMessage {
+    pub chat_id: i64,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_thread_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parse_mode: Option<ParseMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_preview_options: Option<LinkPreviewOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_notification: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protect_content: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to_message_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_markup: Option<ReplyMarkup>,
+}
+request!(SendMessage, "sendMessage", Message);
+
+#[allow(dead_code)]
+impl SendMessage {
+    pub fn new(chat_id: i64, text: impl Into<String>) -> Self {
+        Self {
+            chat_id,
+            text: text.into(),
+            message_thread_id: None,
+            parse_mode: None,
+            link_preview_options: None,
+            disable_notification: None,
+            protect_content: None,
+            reply_to_message_id: None,
+            reply_markup: None,
+        }
+    }
+
+    setters!(
+        message_thread_id: i64,
+        parse_mode: ParseMode,
+        link_preview_options: LinkPreviewOptions,
+        disable_notification: bool,
+        protect_content: bool,
+        reply_to_message_id: i64,
+        reply_markup: ReplyMarkup,
+    );
+
+    pub fn disable_link_preview(self) -> Self {
+        self.link_preview_options(LinkPreviewOptions { is_disabled: Some(true), ..Default::default() })
+    }
+}
+
+/// Declares a send-media request. The main file field and, when `thumbnail` is
+/// given, the thumbnail are `InputFile`s; the braces list method-specific options.
+macro_rules! media_request {
+    ($name:ident, $method:literal, $field:ident, thumbnail, { $($extra:ident: $ety:ty),* $(,)? }) => {
+        media_request!(@define $name, $method, $field, [thumbnail], { thumbnail: InputFile, $($extra: $ety),* });
+    };
+    ($name:ident, $method:literal, $field:ident, { $($extra:ident: $ety:ty),* $(,)? }) => {
+        media_request!(@define $name, $method, $field, [], { $($extra: $ety),* });
+    };
+    (@define $name:ident, $method:literal, $field:ident, [$($thumb:ident)?], { $($extra:ident: $ety:ty),* }) => {
+        #[derive(Serialize, Debug, Clone)]
+        pub struct $name {
+            pub chat_id: i64,
+            pub $field: InputFile,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub message_thread_id: Option<i64>,
+            $(
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub $extra: Option<$ety>,
+            )*
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub disable_notification: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub protect_content: Option<bool>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reply_to_message_id: Option<i64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reply_markup: Option<ReplyMarkup>,
+        }
+
+        impl Request for $name {
+            type Response = Message;
+            const METHOD: &'static str = $method;
+
+            fn files(&self) -> Vec<(&'static str, &InputFile)> {
+                #[allow(unused_mut)]
+                let mut files = vec![(stringify!($field), &self.$field)];
+                $(
+                    if let Some(f) = &self.$thumb {
+                        files.push((stringify!($thumb), f));
+                    }
+                )?
+                files
+            }
+        }
+
+        #[allow(dead_code)]
+        impl $name {
+            pub fn new(chat_id: i64, $field: InputFile) -> Self {
+                Self {
+                    chat_id,
+                    $field,
+                    message_thread_id: None,
+                    $($extra: None,)*
+                    disable_notification: None,
+                    protect_content: None,
+                    reply_to_message_id: None,
+                    reply_markup: None,
+                }
+            }
+
+            $(
+                pub fn $extra(mut self, $extra: impl Into<$ety>) -> Self {
+                    self.$extra = Some($extra.into());
+                    self
+                }
+            )*
+
+            setters!(
+                message_thread_id: i64,
+                disable_notification: bool,
+                protect_content: bool,
+                reply_to_message_id: i64,
+                reply_markup: ReplyMarkup,
+            );
+        }
+    };
+}
+
+media_request!(SendDocument, "sendDocument", document, thumbnail, {
+    caption: String,
+    parse_mode: ParseMode,
+    disable_content_type_detection: bool,
+});
+media_request!(SendPhoto, "sendPhoto", photo, {
+    caption: String,
+    parse_mode: ParseMode,
+    has_spoiler: bool,
+});
+media_request!(SendAudio, "sendAudio", audio, thumbnail, {
+    caption: String,
+    parse_mode: ParseMode,
+    duration: u32,
+    performer: String,
+    title: String,
+});
+media_request!(SendVoice, "sendVoice", voice, {
+    caption: String,
+    parse_mode: ParseMode,
+    duration: u32,
+});
+media_request!(SendVideo, "sendVideo", video, thumbnail, {
+    caption: String,
+    parse_mode: ParseMode,
+    duration: u32,
+    width: u32,
+    height: u32,
+    has_spoiler: bool,
+    supports_streaming: bool,
+});
+media_request!(SendAnimation, "sendAnimation", animation, thumbnail, {
+    caption: String,
+    parse_mode: ParseMode,
+    duration: u32,
+    width: u32,
+    height: u32,
+    has_spoiler: bool,
+});
+media_request!(SendVideoNote, "sendVideoNote", video_note, thumbnail, {
+    duration: u32,
+    length: u32,
+});
+media_request!(SendSticker, "sendSticker", sticker, {
+    emoji: String,
+});
+
+/// Declares an edit request that targets either `chat_id` + `message_id` or an
+/// `inline_message_id`, followed by its method-specific fields.
+macro_rules! edit_request {
+    ($name:ident, $method:literal, { $($field:ident: $fty:ty),* $(,)? }, { $($extra:ident: $ety:ty),* $(,)? }) => {
+        #[derive(Serialize, Debug, Clone)]
+        pub struct $name {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub chat_id: Option<i64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub message_id: Option<i64>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub inline_message_id: Option<String>,
+            $(pub $field: $fty,)*
+            $(
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub $extra: Option<$ety>,
+            )*
+            #[serde(skip_serializing_if = "Option::is_none")]
+            pub reply_markup: Option<InlineKeyboardMarkup>,
+        }
+        request!($name, $method, EditResult);
+
+        #[allow(dead_code)]
+        impl $name {
+            pub fn new(chat_id: i64, message_id: i64, $($field: impl Into<$fty>),*) -> Self {
+                Self {
+                    chat_id: Some(chat_id),
+                    message_id: Some(message_id),
+                    inline_message_id: None,
+                    $($field: $field.into(),)*
+                    $($extra: None,)*
+                    reply_markup: None,
+                }
+            }
+
+            pub fn inline(inline_message_id: impl Into<String>, $($field: impl Into<$fty>),*) -> Self {
+                Self {
+                    chat_id: None,
+                    message_id: None,
+                    inline_message_id: Some(inline_message_id.into()),
+                    $($field: $field.into(),)*
+                    $($extra: None,)*
+                    reply_markup: None,
+                }
+            }
+
+            $(
+                pub fn $extra(mut self, $extra: impl Into<$ety>) -> Self {
+                    self.$extra = Some($extra.into());
+                    self
+                }
+            )*
+
+            setters!(reply_markup: InlineKeyboardMarkup);
+        }
+    };
+}
+
+edit_request!(EditMessageText, "editMessageText", { text: String }, {
+    parse_mode: ParseMode,
+    link_preview_options: LinkPreviewOptions,
+});
+edit_request!(EditMessageCaption, "editMessageCaption", {}, {
+    caption: String,
+    parse_mode: ParseMode,
+});
+edit_request!(EditMessageReplyMarkup, "editMessageReplyMarkup", {}, {});
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DeleteMessage {
+    pub chat_id: i64,
+    pub message_id: i64,
+}
+request!(DeleteMessage, "deleteMessage", bool);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DeleteMessages {
+    pub chat_id: i64,
+    pub message_ids: Vec<i64>,
+}
+request!(DeleteMessages, "deleteMessages", bool);
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GetMe {}
+request!(GetMe, "getMe", User);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GetChat {
+    pub chat_id: i64,
+}
+request!(GetChat, "getChat", ChatFullInfo);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GetUserProfilePhotos {
+    pub user_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+request!(GetUserProfilePhotos, "getUserProfilePhotos", UserProfilePhotos);
+
+#[allow(dead_code)]
+impl GetUserProfilePhotos {
+    pub fn new(user_id: i64) -> Self {
+        Self { user_id, offset: None, limit: None }
+    }
+
+    setters!(offset: u32, limit: u32);
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GetFile {
+    pub file_id: String,
+}
+request!(GetFile, "getFile", File);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnswerCallbackQuery {
+    pub callback_query_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_alert: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_time: Option<i32>,
+}
+request!(AnswerCallbackQuery, "answerCallbackQuery", bool);
+
+#[allow(dead_code)]
+impl AnswerCallbackQuery {
+    pub fn new(callback_query_id: impl Into<String>) -> Self {
+        Self { callback_query_id: callback_query_id.into(), text: None, show_alert: None, url: None, cache_time: None }
+    }
+
+    setters!(text: impl Into<String>, show_alert: bool, url: impl Into<String>, cache_time: i32);
+}
+
+/// Telegram accepts at most this many results per answer.
+pub const MAX_INLINE_RESULTS: usize = 50;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnswerInlineQuery {
+    pub inline_query_id: String,
+    pub results: Vec<InlineQueryResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_time: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_personal: Option<bool>,
+    /// Sent back as `InlineQuery::offset` when the user scrolls to the end of
+    /// the results; empty when there are no more.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub button: Option<InlineQueryResultsButton>,
+}
+request!(AnswerInlineQuery, "answerInlineQuery", bool);
+
+#[allow(dead_code)]
+impl AnswerInlineQuery {
+    pub fn new(inline_query_id: impl Into<String>, results: Vec<InlineQueryResult>) -> Self {
+        Self { inline_query_id: inline_query_id.into(), results, cache_time: None, is_personal: None, next_offset: None, button: None }
+    }
+
+    /// Answers `query` with the page of `results` its offset asks for, at most
+    /// `page_size` long, and sets `next_offset` to the page after it. The
+    /// offset is the index of the first result, so `results` must come out in
+    /// the same order for every page of a query.
+    pub fn page(query: &InlineQuery, mut results: Vec<InlineQueryResult>, page_size: usize) -> Self {
+        let page_size = page_size.clamp(1, MAX_INLINE_RESULTS);
+        let start = query.offset.parse::<usize>().unwrap_or(0).min(results.len());
+        let end = (start + page_size).min(results.len());
+        let next_offset = if end < results.len() { end.to_string() } else { String::new() };
+        results.truncate(end);
+        Self::new(query.id.clone(), results.split_off(start)).next_offset(next_offset)
+    }
+
+    /// Results are cached per user instead of for everyone sending the same query.
+    pub fn personal(mut self, is_personal: bool) -> Self {
+        self.is_personal = Some(is_personal);
+        self
+    }
+
+    setters!(cache_time: u32, next_offset: impl Into<String>, button: InlineQueryResultsButton);
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SendPoll {
+    pub chat_id: i64,
+    pub question: String,
+    pub options: Vec<InputPollOption>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_thread_id: Option<i64>,
+    /// Defaults to `true`; only non-anonymous polls send `poll_answer` updates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_anonymous: Option<bool>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub kind: Option<PollKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allows_multiple_answers: Option<bool>,
+    /// 0-based index of the right answer; required for quizzes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correct_option_id: Option<u32>,
+    /// Shown after a wrong quiz answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
+    /// Seconds the poll stays open, 5 to 600. Not together with `close_date`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_period: Option<u32>,
+    /// Unix time the poll closes at, 5 to 600 seconds ahead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub close_date: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_closed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_notification: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protect_content: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_to_message_id: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_markup: Option<ReplyMarkup>,
+}
+request!(SendPoll, "sendPoll", Message);
+
+#[allow(dead_code)]
+impl SendPoll {
+    pub fn new(chat_id: i64, question: impl Into<String>, options: Vec<String>) -> Self {
+        Self {
+            chat_id,
+            question: question.into(),
+            options: options.into_iter().map(|text| InputPollOption { text }).collect(),
+            message_thread_id: None,
+            is_anonymous: None,
+            kind: None,
+            allows_multiple_answers: None,
+            correct_option_id: None,
+            explanation: None,
+            open_period: None,
+            close_date: None,
+            is_closed: None,
+            disable_notification: None,
+            protect_content: None,
+            reply_to_message_id: None,
+            reply_markup: None,
+        }
+    }
+
+    /// A quiz whose right answer is `options[correct_option_id]`.
+    pub fn quiz(chat_id: i64, question: impl Into<String>, options: Vec<String>, correct_option_id: u32) -> Self {
+        let mut poll = Self::new(chat_id, question, options);
+        poll.kind = Some(PollKind::Quiz);
+        poll.correct_option_id = Some(correct_option_id);
+        poll
+    }
+
+    pub fn anonymous(mut self, is_anonymous: bool) -> Self {
+        self.is_anonymous = Some(is_anonymous);
+        self
+    }
+
+    pub fn closed(mut self, is_closed: bool) -> Self {
+        self.is_closed = Some(is_closed);
+        self
+    }
+
+    setters!(
+        message_thread_id: i64,
+        allows_multiple_answers: bool,
+        explanation: impl Into<String>,
+        open_period: u32,
+        close_date: i64,
+        disable_notification: bool,
+        protect_content: bool,
+        reply_to_message_id: i64,
+        reply_markup: ReplyMarkup,
+    );
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StopPoll {
+    pub chat_id: i64,
+    pub message_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_markup: Option<InlineKeyboardMarkup>,
+}
+request!(StopPoll, "stopPoll", Poll);
+
+/// Currency code of Telegram Stars. Stars invoices have no provider token and a
+/// single price.
+pub const STARS: &str = "XTR";
+
+/// What is being sold: the parts `sendInvoice` and `createInvoiceLink` share.
+/// `payload` comes back in the pre-checkout query and the successful payment,
+/// and is never shown to the user.
+#[derive(Serialize, Debug, Clone)]
+pub struct InvoiceParams {
+    pub title: String,
+    pub description: String,
+    pub payload: String,
+    /// Omitted for payments in Stars.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_token: Option<String>,
+    pub currency: String,
+    pub prices: Vec<LabeledPrice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tip_amount: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggested_tip_amounts: Option<Vec<i64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provider_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub photo_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub need_name: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub need_phone_number: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub need_email: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub need_shipping_address: Option<bool>,
+    /// The price depends on the shipping address, so Telegram sends a shipping query.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_flexible: Option<bool>,
+}
+
+#[allow(dead_code)]
+impl InvoiceParams {
+    pub fn new(
+        title: impl Into<String>,
+        description: impl Into<String>,
+        payload: impl Into<String>,
+        currency: impl Into<String>,
+        prices: Vec<LabeledPrice>,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            description: description.into(),
+            payload: payload.into(),
+            provider_token: None,
+            currency: currency.into(),
+            prices,
+            max_tip_amount: None,
+            suggested_tip_amounts: None,
+            provider_data: None,
+            photo_url: None,
+            need_name: None,
+            need_phone_number: None,
+            need_email: None,
+            need_shipping_address: None,
+            is_flexible: None,
+        }
+    }
+
+    /// An invoice for `amount` Telegram Stars.
+    pub fn stars(title: impl Into<String>, description: impl Into<String>, payload: impl Into<String>, amount: i64) -> Self {
+        let title = title.into();
+        Self::new(title.clone(), description, payload, STARS, vec![LabeledPrice::new(title, amount)])
+    }
+
+    pub fn flexible(mut self, is_flexible: bool) -> Self {
+        self.is_flexible = Some(is_flexible);
+        self
+    }
+
+    setters!(
+        provider_token: impl Into<String>,
+        max_tip_amount: i64,
+        suggested_tip_amounts: Vec<i64>,
+        provider_data: impl Into<String>,
+        photo_url: impl Into<String>,
+        need_name: bool,
+        need_phone_number: bool,
+        need_email: bool,
+        need_shipping_address: bool,
+    );
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SendInvoice {
+    pub chat_id: i64,
+    #[serde(flatten)]
+    pub invoice: InvoiceParams,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message_thread_id: Option<i64>,
+    /// Sent as `/start <start_parameter>` when the invoice is forwarded and
+    /// someone else presses Pay.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_parameter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_notification: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protect_content: Option<bool>,
+    /// The first button has to be the Pay button.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reply_markup: Option<InlineKeyboardMarkup>,
+}
+request!(SendInvoice, "sendInvoice", Message);
+
+#[allow(dead_code)]
+impl SendInvoice {
+    pub fn new(chat_id: i64, invoice: InvoiceParams) -> Self {
+        Self {
+            chat_id,
+            invoice,
+            message_thread_id: None,
+            start_parameter: None,
+            disable_notification: None,
+            protect_content: None,
+            reply_markup: None,
+        }
+    }
+
+    setters!(
+        message_thread_id: i64,
+        start_parameter: impl Into<String>,
+        disable_notification: bool,
+        protect_content: bool,
+        reply_markup: InlineKeyboardMarkup,
+    );
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CreateInvoiceLink {
+    #[serde(flatten)]
+    pub invoice: InvoiceParams,
+    /// Seconds between charges of a Stars subscription; Telegram only accepts 30 days.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_period: Option<u32>,
+}
+request!(CreateInvoiceLink, "createInvoiceLink", String);
+
+#[allow(dead_code)]
+impl CreateInvoiceLink {
+    pub fn new(invoice: InvoiceParams) -> Self {
+        Self { invoice, subscription_period: None }
+    }
+
+    setters!(subscription_period: u32);
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnswerShippingQuery {
+    pub shipping_query_id: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shipping_options: Option<Vec<ShippingOption>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+}
+request!(AnswerShippingQuery, "answerShippingQuery", bool);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct AnswerPreCheckoutQuery {
+    pub pre_checkout_query_id: String,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_message: Option<String>,
+}
+request!(AnswerPreCheckoutQuery, "answerPreCheckoutQuery", bool);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RefundStarPayment {
+    pub user_id: i64,
+    pub telegram_payment_charge_id: String,
+}
+request!(RefundStarPayment, "refundStarPayment", bool);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SetWebhook {
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_connections: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed_updates: Option<Vec<UpdateKind>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drop_pending_updates: Option<bool>,
+}
+request!(SetWebhook, "setWebhook", bool);
+
+#[allow(dead_code)]
+impl SetWebhook {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), secret_token: None, max_connections: None, allowed_updates: None, drop_pending_updates: None }
+    }
+
+    setters!(secret_token: impl Into<String>, max_connections: u32, allowed_updates: Vec<UpdateKind>, drop_pending_updates: bool);
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DeleteWebhook {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub drop_pending_updates: Option<bool>,
+}
+request!(DeleteWebhook, "deleteWebhook", bool);
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct GetWebhookInfo {}
+request!(GetWebhookInfo, "getWebhookInfo", WebhookInfo);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SetMyCommands {
+    pub commands: Vec<BotCommand>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_code: Option<String>,
+}
+request!(SetMyCommands, "setMyCommands", bool);
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct DeleteMyCommands {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<BotCommandScope>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_code: Option<String>,
+}
+request!(DeleteMyCommands, "deleteMyCommands", bool);
@@ -1,5 +1,5 @@
-use crate::client::Client;
-use crate::dispatch::Dispatcher;
+use crate::client::{Client, InputFile};
+use crate::dispatch::{CommandSpec, Dispatcher};
 use crate::types::Message;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -10,20 +10,30 @@ type Counters = Arc<RwLock<HashMap<String, u64>>>;
 
 pub fn register(disp: &mut Dispatcher, admin: Option<i64>, users: Users, counters: Counters) {
     let users_clone = users.clone();
-    disp.add_command("broadcast", move |client: Client, msg: Message| {
+    disp.add_command(CommandSpec::new("broadcast", "send to all users").arg("text").admin(), move |client: Client, msg: Message| {
         let users = users_clone.clone();
         let admin = admin;
         async move {
             if admin.is_none() { let _ = client.send_message(msg.chat.id, "ADMIN_ID not set", None).await; return Ok(()); }
             let allowed = msg.from.as_ref().map(|u| Some(u.id) == admin).unwrap_or(false);
             if !allowed { let _ = client.send_message(msg.chat.id, "not allowed", None).await; return Ok(()); }
-            if let Some(text) = msg.text {
+            if let Some(text) = msg.text_or_caption() {
                 let parts: Vec<&str> = text.splitn(2, ' ').collect();
                 if parts.len() < 2 { let _ = client.send_message(msg.chat.id, "usage: /broadcast <text>", None).await; return Ok(()); }
                 let body = parts[1];
                 let list: Vec<i64> = users.read().await.iter().cloned().collect();
+                let mut gone = Vec::new();
                 for uid in list {
-                    let _ = client.send_message(uid, body, None).await;
+                    if let Err(e) = client.send_message(uid, body, None).await {
+                        if e.is_blocked_by_user() || e.is_user_deactivated() || e.is_chat_not_found() || e.is_kicked_from_chat() {
+                            gone.push(uid);
+                        }
+                    }
+                }
+                if !gone.is_empty() {
+                    let mut us = users.write().await;
+                    for uid in &gone { us.remove(uid); }
+                    let _ = client.send_message(msg.chat.id, &format!("removed {} unreachable chats", gone.len()), None).await;
                 }
             }
             Ok(())
@@ -31,7 +41,7 @@ pub fn register(disp: &mut Dispatcher, admin: Option<i64>, users: Users, counter
     });
 
     let admin_for_inspect = admin;
-    disp.add_command("inspect", move |client: Client, msg: Message| {
+    disp.add_command(CommandSpec::new("inspect", "show chat info and profile photo of a user").optional_arg("user_id").admin(), move |client: Client, msg: Message| {
         let admin = admin_for_inspect;
         async move {
             let allowed = msg.from.as_ref().map(|u| Some(u.id) == admin).unwrap_or(false);
@@ -40,7 +50,7 @@ pub fn register(disp: &mut Dispatcher, admin: Option<i64>, users: Users, counter
                 return Ok(());
             }
 
-            let target_id_opt = if let Some(text) = &msg.text {
+            let target_id_opt = if let Some(text) = msg.text_or_caption() {
                 let parts: Vec<&str> = text.split_whitespace().collect();
                 if parts.len() > 1 {
                     parts[1].parse::<i64>().ok()
@@ -64,7 +74,7 @@ pub fn register(disp: &mut Dispatcher, admin: Option<i64>, users: Users, counter
                 Ok(uph) => {
                     report.push_str(&format!("profile_photos_total: {}\n", uph.total_count));
                     if uph.total_count > 0 {
-                        if let Some(sizes) = uph.photos.get(0) {
+                        if let Some(sizes) = uph.photos.first() {
                             if let Some(best) = sizes.last().cloned() {
                                 report.push_str(&format!("chosen_photo_file_id: {}\n", best.file_id));
                                 if let Ok(finfo) = client.get_file(&best.file_id).await {
@@ -94,15 +104,14 @@ pub fn register(disp: &mut Dispatcher, admin: Option<i64>, users: Users, counter
         }
     });
 
-    disp.add_command("upload", |client: Client, msg: Message| async move {
-        let path = "README.md";
-        client.send_document_path(msg.chat.id, path).await?;
+    disp.add_command(CommandSpec::new("upload", "upload README.md"), |client: Client, msg: Message| async move {
+        client.send_document(msg.chat.id, InputFile::path("README.md")).await?;
         Ok(())
     });
 
     let users_c = users.clone();
     let counters_c = counters.clone();
-    disp.add_command("stats", move |client: Client, msg: Message| {
+    disp.add_command(CommandSpec::new("stats", "show simple stats"), move |client: Client, msg: Message| {
         let users = users_c.clone();
         let counters = counters_c.clone();
         async move {
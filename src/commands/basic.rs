@@ -1,6 +1,7 @@
-use crate::client::Client;
-use crate::dispatch::Dispatcher;
-use crate::types::{Message, ReplyMarkup};
+use crate::client::{Client, InputFile};
+use crate::dispatch::{CommandSpec, Dispatcher, Visibility};
+use crate::format::Text;
+use crate::types::{InlineKeyboardButton, InlineKeyboardMarkup, Message, ReplyMarkup};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::{HashSet};
@@ -13,38 +14,41 @@ pub fn register(
     kb_start: ReplyMarkup,
     kb_keyboard: ReplyMarkup,
 ) {
-    disp.add_command("help", move |client: Client, msg: Message| {
+    let registry = disp.registry();
+    disp.add_command(CommandSpec::new("help", "this message (use /help <command> for detail)").optional_arg("command"), move |client: Client, msg: Message| {
         let admin = admin;
         let kb = kb_help.clone();
+        let registry = registry.clone();
         async move {
-            let mut help = String::from("<pre>Available commands:\n");
-            help.push_str("/start - start and register\n");
-            help.push_str("/help - this message (use /help <command> for detail)\n");
-            help.push_str("/ping - pong\n");
-            help.push_str("/echo &lt;text&gt; - echo back text\n");
-            help.push_str("/whoami - show your id and username\n");
-            help.push_str("/keyboard - show custom keyboard\n");
-            help.push_str("/inline - show inline buttons example\n");
-            help.push_str("/set &lt;k&gt; &lt;v&gt; - save key/value (persisted)\n");
-            help.push_str("/get &lt;k&gt; - get saved value\n");
-            help.push_str("/broadcast &lt;text&gt; - send to all users (admin only)\n");
-            help.push_str("/upload - upload README.md\n");
-            help.push_str("/stats - show simple stats\n");
-            if admin.is_some() {
-                help.push_str("\nAdmin commands are enabled.\n");
-            } else {
-                help.push_str("\nNote: ADMIN_ID not set. Some commands require ADMIN_ID.\n");
-            }
-            help.push_str("</pre>");
-            let rm = serde_json::to_value(&kb).ok();
-            let _ = client.send_message_html(msg.chat.id, &help, rm).await?;
+            let is_admin = admin.is_some() && msg.from.as_ref().map(|u| Some(u.id) == admin).unwrap_or(false);
+            let topic = msg.text_or_caption().and_then(|t| t.split_whitespace().nth(1));
+            let help = match topic {
+                Some(name) => match registry.get(name).filter(|c| c.is_listed() && (is_admin || c.visibility == Visibility::Public)) {
+                    Some(spec) => help_detail(&spec),
+                    None => format!("Unknown command: /{}\nUse /help to list commands.\n", name.trim_start_matches('/')),
+                },
+                None => {
+                    let mut help = String::from("Available commands:\n");
+                    for spec in registry.listed(is_admin) {
+                        let suffix = if spec.visibility == Visibility::Admin { " (admin only)" } else { "" };
+                        help.push_str(&format!("{} - {}{}\n", spec.usage_line(), spec.description, suffix));
+                    }
+                    if admin.is_some() {
+                        help.push_str("\nAdmin commands are enabled.\n");
+                    } else {
+                        help.push_str("\nNote: ADMIN_ID not set. Some commands require ADMIN_ID.\n");
+                    }
+                    help
+                }
+            };
+            client.send_formatted(msg.chat.id, &Text::new().pre(help, None), Some(kb)).await?;
             Ok(())
         }
     });
 
     let users_for_start = _users.clone();
     let admin_for_start = admin;
-    disp.add_command("start", move |client: Client, msg: Message| {
+    disp.add_command(CommandSpec::new("start", "start and register"), move |client: Client, msg: Message| {
         let users = users_for_start.clone();
         let kb = kb_start.clone();
         let admin = admin_for_start;
@@ -53,8 +57,7 @@ pub fn register(
             us.insert(msg.chat.id);
             let name = msg.from.as_ref().map(|u| u.first_name.clone()).unwrap_or_else(|| "there".to_string());
             let welcome = format!("Hello, {}! Welcome. Type /help to see available commands.", name);
-            let rm = serde_json::to_value(&kb).ok();
-            client.send_message(msg.chat.id, &welcome, rm).await?;
+            client.send_message(msg.chat.id, &welcome, Some(kb)).await?;
 
             let mut info = String::new();
             info.push_str("New /start received:\n\n");
@@ -62,77 +65,96 @@ pub fn register(
             info.push_str(&format!("from: {:?}\n", msg.from));
             info.push_str(&format!("message_id: {}\n", msg.message_id));
             info.push_str(&format!("text: {}\n\n", msg.text.clone().unwrap_or_default()));
-            if let Ok(js) = serde_json::to_string_pretty(&msg) {
+            if let Some(js) = msg.raw().and_then(|v| serde_json::to_string_pretty(v).ok()) {
                 info.push_str("raw_json:\n");
                 info.push_str(&js);
-                info.push_str("\n");
+                info.push('\n');
             }
 
             if let Some(aid) = admin {
                 let _ = if info.len() < 3500 {
                     client.send_message(aid, &info, None).await
                 } else {
-                    let mut path = std::env::temp_dir();
                     let fname = format!("start_info_{}.json", msg.chat.id);
-                    path.push(fname);
-                    let path_str = path.to_string_lossy().to_string();
-                    let _ = tokio::fs::write(&path_str, info.as_bytes()).await;
-                    client.send_document_path(aid, &path_str).await
+                    client.send_document(aid, InputFile::bytes(fname, info)).await
                 };
             }
             Ok(())
         }
     });
 
-    disp.add_command("ping", |client: Client, msg: Message| async move {
+    disp.add_command(CommandSpec::new("ping", "pong"), |client: Client, msg: Message| async move {
         client.send_message(msg.chat.id, "pong", None).await?;
         Ok(())
     });
 
-    disp.add_command("echo", |client: Client, msg: Message| async move {
-        if let Some(text) = msg.text {
+    disp.add_command(CommandSpec::new("echo", "echo back text").arg("text"), |client: Client, msg: Message| async move {
+        if let Some(text) = msg.text_or_caption() {
             let parts: Vec<&str> = text.splitn(2, ' ').collect();
             let resp = if parts.len() > 1 { parts[1].to_string() } else { "".to_string() };
-            client.send_message(msg.chat.id, &resp, None).await?;
+            client.send_formatted(msg.chat.id, &Text::from(resp), None).await?;
         }
         Ok(())
     });
 
-    disp.add_command("whoami", |client: Client, msg: Message| async move {
+    disp.add_command(CommandSpec::new("whoami", "show your id and username"), |client: Client, msg: Message| async move {
         let user = msg.from;
         if let Some(u) = user {
             let name = u.username.clone().unwrap_or_else(|| u.first_name.clone());
-            let resp = format!("id: {}\nusername: {}", u.id, name);
-            client.send_message(msg.chat.id, &resp, None).await?;
+            let resp = Text::new().text("id: ").code(u.id.to_string()).text("\nusername: ").mention(u.id, name);
+            client.send_formatted(msg.chat.id, &resp, None).await?;
         }
         Ok(())
     });
 
-    disp.add_command("keyboard", move |client: Client, msg: Message| {
+    disp.add_command(CommandSpec::new("keyboard", "show custom keyboard"), move |client: Client, msg: Message| {
         let rm = kb_keyboard.clone();
         async move {
-            let rmv = serde_json::to_value(&rm).ok();
-            client.send_message(msg.chat.id, "Choose:", rmv).await?;
+            client.send_message(msg.chat.id, "Choose:", Some(rm)).await?;
             Ok(())
         }
     });
 
-    disp.add_command("inline", |client: Client, msg: Message| async move {
-        let inline = ReplyMarkup::InlineKeyboard(crate::types::InlineKeyboardMarkup {
-            inline_keyboard: vec![vec![crate::types::InlineKeyboardButton { text: "Say hi".to_string(), callback_data: Some("echo Hello from button".to_string()), url: None }]]
-        });
-        let rm = serde_json::to_value(&inline).ok();
-        client.send_message(msg.chat.id, "Inline example:", rm).await?;
+    disp.add_command(CommandSpec::new("inline", "show inline buttons example"), |client: Client, msg: Message| async move {
+        let inline = ReplyMarkup::InlineKeyboard(inline_menu());
+        client.send_message(msg.chat.id, "Inline example:", Some(inline)).await?;
         Ok(())
     });
 
     disp.add_callback(|client: Client, cb: crate::types::CallbackQuery| async move {
         let _ = client.answer_callback_query(&cb.id, Some("Received"), Some(false), None, None).await;
-        if let Some(msg) = cb.message {
-            let chat_id = msg.chat.id;
-            let d = cb.data.unwrap_or_else(|| "(no data)".to_string());
-            client.send_message(chat_id, &format!("Button pressed: {}", d), None).await?;
+        let d = cb.data.clone().unwrap_or_else(|| "(no data)".to_string());
+        if d == "delete" {
+            if let Some(msg) = &cb.message {
+                client.delete_message(msg.chat.id, msg.message_id).await?;
+            }
+            return Ok(());
         }
+        client.edit_callback_message(&cb, &format!("Button pressed: {}", d), Some(inline_menu())).await?;
         Ok(())
     });
 }
+
+fn inline_menu() -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup {
+        inline_keyboard: vec![vec![
+            InlineKeyboardButton { text: "Say hi".to_string(), callback_data: Some("echo Hello from button".to_string()), ..Default::default() },
+            InlineKeyboardButton { text: "Delete".to_string(), callback_data: Some("delete".to_string()), ..Default::default() },
+        ]],
+        ..Default::default()
+    }
+}
+
+fn help_detail(spec: &CommandSpec) -> String {
+    let mut out = format!("{}\n{}\n", spec.usage_line(), spec.description);
+    if !spec.args.is_empty() {
+        out.push_str("\nArguments:\n");
+        for a in &spec.args {
+            out.push_str(&format!("  {}{}\n", a.name, if a.required { "" } else { " (optional)" }));
+        }
+    }
+    if spec.visibility == Visibility::Admin {
+        out.push_str("\nAdmin only.\n");
+    }
+    out
+}
@@ -1,6 +1,8 @@
 pub mod basic;
 pub mod store;
 pub mod admin;
+pub mod payments;
+pub mod polls;
 
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -9,13 +11,16 @@ use crate::dispatch::Dispatcher;
 use crate::types::ReplyMarkup;
 
 type KvStore = Arc<RwLock<HashMap<String, String>>>;
+type KvOwners = Arc<RwLock<HashMap<String, i64>>>;
 type Users = Arc<RwLock<HashSet<i64>>>;
 type Counters = Arc<RwLock<HashMap<String, u64>>>;
 
+#[allow(clippy::too_many_arguments)]
 pub fn register(
     disp: &mut Dispatcher,
     admin: Option<i64>,
     kv: KvStore,
+    kv_owners: KvOwners,
     users: Users,
     counters: Counters,
     kb_help: ReplyMarkup,
@@ -23,6 +28,146 @@ pub fn register(
     kb_keyboard: ReplyMarkup,
 ) {
     basic::register(disp, admin, users.clone(), kb_help, kb_start, kb_keyboard);
-    store::register(disp, kv);
+    store::register(disp, kv, kv_owners);
     admin::register(disp, admin, users, counters);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{poll, MockBotApi, MOCK_BOT_USERNAME};
+    use crate::types::ReplyKeyboardMarkup;
+
+    fn dispatcher() -> Dispatcher {
+        let kb = ReplyMarkup::ReplyKeyboard(ReplyKeyboardMarkup { keyboard: Vec::new(), one_time_keyboard: None, resize_keyboard: None });
+        let mut disp = Dispatcher::new();
+        register(&mut disp, None, Default::default(), Default::default(), Default::default(), Default::default(), kb.clone(), kb.clone(), kb);
+        disp
+    }
+
+    #[tokio::test]
+    async fn ping_replies_pong() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        api.push_message(42, 42, "/ping");
+        poll(&client, &dispatcher()).await;
+
+        let sent = api.wait_for("sendMessage", 1).await;
+        assert_eq!(sent[0].i64("chat_id"), Some(42));
+        assert_eq!(sent[0].str("text"), Some("pong"));
+    }
+
+    #[tokio::test]
+    async fn commands_for_other_bots_are_ignored() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        client.set_me(client.get_me().await.unwrap());
+        let disp = dispatcher();
+        api.push_message(-100, 42, "/echo@other_bot first");
+        api.push_message(-100, 42, &format!("/echo@{} second", MOCK_BOT_USERNAME));
+        api.push_message(-100, 42, "/ping");
+        poll(&client, &disp).await;
+
+        // Two replies, and no handler left running that could still send a third.
+        api.wait_for("sendMessage", 2).await;
+        let in_flight = disp.in_flight();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while in_flight.load(std::sync::atomic::Ordering::SeqCst) > 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("handlers still running");
+        let mut texts: Vec<String> = api.calls_to("sendMessage").iter().filter_map(|c| c.str("text").map(String::from)).collect();
+        texts.sort();
+        assert_eq!(texts, vec!["pong", "second"]);
+    }
+
+    #[tokio::test]
+    async fn delete_button_answers_and_deletes() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        api.push_callback(42, 42, "delete");
+        poll(&client, &dispatcher()).await;
+
+        api.wait_for("deleteMessage", 1).await;
+        let answers = api.calls_to("answerCallbackQuery");
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].str("text"), Some("Received"));
+    }
+
+    #[tokio::test]
+    async fn commands_in_captions_are_dispatched() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        api.push_update(serde_json::json!({ "message": {
+            "message_id": 7,
+            "date": 0,
+            "chat": { "id": 42, "type": "private" },
+            "from": { "id": 42, "is_bot": false, "first_name": "U" },
+            "photo": [{ "file_id": "small", "file_unique_id": "s", "width": 90, "height": 90 }],
+            "caption": "/echo hi",
+            "caption_entities": [{ "type": "bot_command", "offset": 0, "length": 5 }],
+        } }));
+        poll(&client, &dispatcher()).await;
+
+        let sent = api.wait_for("sendMessage", 1).await;
+        assert_eq!(sent[0].str("text"), Some("hi"));
+    }
+
+    #[tokio::test]
+    async fn inline_query_searches_store_by_page() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let disp = dispatcher();
+        for i in 0..25 {
+            api.push_message(42, 42, &format!("/set key{:02} value {}", i, i));
+        }
+        api.push_message(42, 42, "/set other thing");
+        poll(&client, &disp).await;
+        // /set does not reply, so wait for the handlers through a reply that comes after them.
+        api.push_message(42, 42, "/get key24");
+        poll(&client, &disp).await;
+        api.wait_for("sendMessage", 1).await;
+
+        api.push_inline_query(42, "KEY", "");
+        poll(&client, &disp).await;
+        let first = api.wait_for("answerInlineQuery", 1).await;
+        let results = first[0].params["results"].as_array().unwrap();
+        assert_eq!(results.len(), 20);
+        assert_eq!(results[0]["type"], "article");
+        assert_eq!(results[0]["title"], "key00");
+        assert_eq!(results[0]["input_message_content"]["message_text"], "value 0");
+        assert_eq!(first[0].str("next_offset"), Some("20"));
+
+        api.push_inline_query(42, "KEY", "20");
+        poll(&client, &disp).await;
+        let second = api.wait_for("answerInlineQuery", 2).await;
+        let results = second[1].params["results"].as_array().unwrap();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[4]["title"], "key24");
+        assert_eq!(second[1].str("next_offset"), Some(""));
+    }
+
+    #[tokio::test]
+    async fn inline_query_only_shows_own_values() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let disp = dispatcher();
+        api.push_message(42, 42, "/set wifi hunter2");
+        api.push_message(43, 43, "/set lunch pizza");
+        poll(&client, &disp).await;
+        api.push_message(42, 42, "/get wifi");
+        api.push_message(43, 43, "/get lunch");
+        poll(&client, &disp).await;
+        api.wait_for("sendMessage", 2).await;
+
+        api.push_inline_query(43, "", "");
+        poll(&client, &disp).await;
+        let answer = &api.wait_for("answerInlineQuery", 1).await[0];
+        let results = answer.params["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["title"], "lunch");
+        assert_eq!(answer.params["is_personal"], true);
+    }
+}
@@ -0,0 +1,191 @@
+use crate::client::requests::{InvoiceParams, STARS};
+use crate::client::Client;
+use crate::dispatch::{CommandSpec, Dispatcher};
+use crate::payments::Ledger;
+use crate::types::{Message, PreCheckoutQuery, ShippingQuery};
+use chrono::DateTime;
+use tokio::time::{Duration, Instant};
+
+/// Invoice payload of premium access.
+pub const PREMIUM: &str = "premium";
+
+// Telegram gives the bot 10 seconds to answer a pre-checkout query; whatever is
+// left after this is for the answer itself.
+const PRE_CHECKOUT_BUDGET: Duration = Duration::from_secs(7);
+
+/// Premium access is sold for `price` Stars with /buy and unlocks /premium.
+pub fn register(disp: &mut Dispatcher, admin: Option<i64>, ledger: Ledger, price: i64) {
+    disp.add_command(CommandSpec::new("buy", "buy premium access"), move |client: Client, msg: Message| async move {
+        let invoice = InvoiceParams::stars("Premium access", "Unlocks the /premium command for good.", PREMIUM, price);
+        client.send_invoice(msg.chat.id, invoice).await?;
+        Ok(())
+    });
+
+    let ledger_premium = ledger.clone();
+    disp.add_command(CommandSpec::new("premium", "premium only"), move |client: Client, msg: Message| {
+        let ledger = ledger_premium.clone();
+        async move {
+            let Some(user) = &msg.from else { return Ok(()) };
+            let reply = match ledger.active(user.id, PREMIUM).await {
+                Some(p) => {
+                    let since = DateTime::from_timestamp(p.paid_at, 0).map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default();
+                    format!("Premium is active since {}. Thank you for your support!", since)
+                }
+                None => "This command needs premium access. Use /buy to get it.".to_string(),
+            };
+            client.send_message(msg.chat.id, &reply, None).await?;
+            Ok(())
+        }
+    });
+
+    // Telegram asks bots that take payments to answer /paysupport.
+    disp.add_command(CommandSpec::new("paysupport", "help with payments"), move |client: Client, msg: Message| async move {
+        let text = match admin {
+            Some(aid) => format!("For questions about a payment or a refund, write to the bot admin (id {}) and include the charge id from your receipt.", aid),
+            None => "For questions about a payment or a refund, contact the bot owner and include the charge id from your receipt.".to_string(),
+        };
+        client.send_message(msg.chat.id, &text, None).await?;
+        Ok(())
+    });
+
+    let ledger_refund = ledger.clone();
+    disp.add_command(CommandSpec::new("refund", "refund a Stars payment").arg("charge_id").admin(), move |client: Client, msg: Message| {
+        let ledger = ledger_refund.clone();
+        async move {
+            let allowed = admin.is_some() && msg.from.as_ref().map(|u| Some(u.id) == admin).unwrap_or(false);
+            if !allowed {
+                client.send_message(msg.chat.id, "not allowed", None).await?;
+                return Ok(());
+            }
+            let Some(charge_id) = msg.text_or_caption().and_then(|t| t.split_whitespace().nth(1)) else {
+                client.send_message(msg.chat.id, "usage: /refund <charge_id>", None).await?;
+                return Ok(());
+            };
+            let reply = match ledger.get(charge_id).await {
+                None => format!("unknown charge {}", charge_id),
+                Some(p) if p.refunded_at.is_some() => format!("{} was already refunded", charge_id),
+                Some(p) if p.currency != STARS => format!("{} was paid in {}; refund it with the payment provider", charge_id, p.currency),
+                Some(p) => {
+                    client.refund_star_payment(p.user_id, &p.charge_id).await?;
+                    ledger.mark_refunded(&p.charge_id).await?;
+                    format!("refunded {} Stars to user {}", p.total_amount, p.user_id)
+                }
+            };
+            client.send_message(msg.chat.id, &reply, None).await?;
+            Ok(())
+        }
+    });
+
+    // Nothing sold here is shipped; Telegram only asks for flexible invoices anyway.
+    disp.add_shipping_query(|client: Client, query: ShippingQuery| async move {
+        client.answer_shipping_query(&query.id, Err("This purchase is delivered digitally and needs no shipping.")).await?;
+        Ok(())
+    });
+
+    let ledger_checkout = ledger.clone();
+    disp.add_pre_checkout_query(move |client: Client, query: PreCheckoutQuery| {
+        let ledger = ledger_checkout.clone();
+        // Taken when the update is dispatched, so time spent waiting for a
+        // handler slot counts against the budget too.
+        let deadline = Instant::now() + PRE_CHECKOUT_BUDGET;
+        async move {
+            let verdict = tokio::time::timeout_at(deadline, check_order(&ledger, &query, price))
+                .await
+                .unwrap_or(Err("The payment could not be checked in time, please try again."));
+            client.answer_pre_checkout_query(&query.id, verdict).await?;
+            Ok(())
+        }
+    });
+
+    disp.add_message(move |client: Client, msg: Message| {
+        let ledger = ledger.clone();
+        async move {
+            if let Some(sp) = &msg.successful_payment {
+                let user_id = msg.from.as_ref().map(|u| u.id).unwrap_or(msg.chat.id);
+                if ledger.record(user_id, sp).await? {
+                    tracing::info!("payment {} of {} {} from {}", sp.telegram_payment_charge_id, sp.total_amount, sp.currency, user_id);
+                    let text = format!("Payment received, premium is unlocked. Charge id: {}", sp.telegram_payment_charge_id);
+                    client.send_message(msg.chat.id, &text, None).await?;
+                    if let Some(aid) = admin {
+                        let note = format!("Payment of {} {} from {}: {}", sp.total_amount, sp.currency, user_id, sp.telegram_payment_charge_id);
+                        let _ = client.send_message(aid, &note, None).await;
+                    }
+                }
+            }
+            // Refunds made outside the bot, e.g. by Telegram support, arrive as this service message.
+            if let Some(rp) = &msg.refunded_payment {
+                ledger.mark_refunded(&rp.telegram_payment_charge_id).await?;
+            }
+            Ok(())
+        }
+    });
+}
+
+async fn check_order(ledger: &Ledger, query: &PreCheckoutQuery, price: i64) -> Result<(), &'static str> {
+    if query.invoice_payload != PREMIUM || query.currency != STARS || query.total_amount != price {
+        return Err("This invoice is out of date. Use /buy to get a new one.");
+    }
+    if ledger.active(query.from.id, PREMIUM).await.is_some() {
+        return Err("You already have premium access.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{poll, MockBotApi, TempDir};
+
+    const ADMIN: i64 = 1;
+    const BUYER: i64 = 42;
+
+    #[tokio::test]
+    async fn sells_and_refunds_premium() {
+        let dir = TempDir::new("payments");
+        let path = dir.path().join("payments.json");
+        let ledger = Ledger::open(&path).await.unwrap();
+        let mut disp = Dispatcher::new();
+        register(&mut disp, Some(ADMIN), ledger.clone(), 50);
+        let api = MockBotApi::start().await;
+        let client = api.client();
+
+        api.push_message(BUYER, BUYER, "/buy");
+        poll(&client, &disp).await;
+        let invoice = &api.wait_for("sendInvoice", 1).await[0];
+        assert_eq!(invoice.str("currency"), Some(STARS));
+        assert_eq!(invoice.str("payload"), Some(PREMIUM));
+        assert_eq!(invoice.params["prices"][0]["amount"], 50);
+        assert!(invoice.params.get("provider_token").is_none());
+
+        api.push_pre_checkout(BUYER, PREMIUM, STARS, 10);
+        api.push_pre_checkout(BUYER, PREMIUM, STARS, 50);
+        poll(&client, &disp).await;
+        let mut answers = api.wait_for("answerPreCheckoutQuery", 2).await;
+        answers.sort_by_key(|c| c.params["ok"].as_bool());
+        assert_eq!(answers[0].params["ok"], false);
+        assert!(answers[0].str("error_message").is_some());
+        assert_eq!(answers[1].params["ok"], true);
+
+        api.push_successful_payment(BUYER, PREMIUM, STARS, 50, "charge-1");
+        poll(&client, &disp).await;
+        let sent = api.wait_for("sendMessage", 2).await;
+        assert!(sent.iter().any(|c| c.i64("chat_id") == Some(ADMIN)));
+        assert_eq!(ledger.active(BUYER, PREMIUM).await.map(|p| p.charge_id), Some("charge-1".to_string()));
+
+        api.push_message(BUYER, BUYER, "/premium");
+        api.push_message(BUYER, BUYER, "/refund charge-1");
+        poll(&client, &disp).await;
+        let sent = api.wait_for("sendMessage", 4).await;
+        assert!(sent[2..].iter().any(|c| c.str("text").unwrap().starts_with("Premium is active")));
+        assert!(sent[2..].iter().any(|c| c.str("text") == Some("not allowed")));
+
+        api.push_message(ADMIN, ADMIN, "/refund charge-1");
+        poll(&client, &disp).await;
+        let refund = &api.wait_for("refundStarPayment", 1).await[0];
+        assert_eq!(refund.i64("user_id"), Some(BUYER));
+        assert_eq!(refund.str("telegram_payment_charge_id"), Some("charge-1"));
+        api.wait_for("sendMessage", 5).await;
+        assert!(ledger.active(BUYER, PREMIUM).await.is_none());
+        assert!(Ledger::open(&path).await.unwrap().get("charge-1").await.unwrap().refunded_at.is_some());
+    }
+}
@@ -0,0 +1,375 @@
+use crate::client::requests::SendPoll;
+use crate::client::{BotError, Client};
+use crate::dispatch::{CommandSpec, Dispatcher};
+use crate::polls::{PollRecord, PollStore};
+use crate::types::{Message, Poll, PollAnswer};
+use crate::utils::parse_args;
+use chrono::Utc;
+use std::collections::BTreeMap;
+
+// Telegram closes a poll by itself at most this far ahead; longer polls are
+// stopped by `close_due`.
+const MAX_CLOSE_DATE_SECS: i64 = 600;
+
+/// What `/poll "question" "option" ...` asks for.
+#[derive(Debug, PartialEq)]
+struct PollArgs {
+    question: String,
+    options: Vec<String>,
+    /// 0-based index of the right answer, for quizzes.
+    correct: Option<u32>,
+    anonymous: bool,
+    multiple: bool,
+    explanation: Option<String>,
+    close_after: Option<i64>,
+}
+
+/// Parses `"question" "option" "option"... [--quiz=N] [--anonymous] [--multiple]
+/// [--explanation=text] [--close=DURATION]`, where N counts options from 1.
+fn parse_poll_args(args: &[String]) -> Result<PollArgs, String> {
+    let mut positional = Vec::new();
+    let mut parsed = PollArgs { question: String::new(), options: Vec::new(), correct: None, anonymous: false, multiple: false, explanation: None, close_after: None };
+    for arg in args {
+        let Some(flag) = arg.strip_prefix("--") else {
+            positional.push(arg.clone());
+            continue;
+        };
+        let (name, value) = match flag.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (flag, None),
+        };
+        match (name, value) {
+            ("anonymous", None) => parsed.anonymous = true,
+            ("multiple", None) => parsed.multiple = true,
+            ("quiz", Some(n)) => match n.parse::<u32>() {
+                Ok(n) if n >= 1 => parsed.correct = Some(n - 1),
+                _ => return Err(format!("--quiz takes the number of the right option, not {:?}", n)),
+            },
+            ("explanation", Some(text)) => parsed.explanation = Some(text.to_string()),
+            ("close", Some(d)) => parsed.close_after = Some(parse_duration(d).ok_or_else(|| format!("bad duration {:?}, use e.g. 90s, 30m, 12h or 7d", d))?),
+            _ => return Err(format!("unknown option --{}", flag)),
+        }
+    }
+    let mut positional = positional.into_iter();
+    parsed.question = positional.next().ok_or("a question is required")?;
+    parsed.options = positional.collect();
+    if parsed.options.len() < 2 {
+        return Err("a poll needs at least two options".to_string());
+    }
+    if let Some(correct) = parsed.correct {
+        if correct as usize >= parsed.options.len() {
+            return Err(format!("--quiz={} but there are only {} options", correct + 1, parsed.options.len()));
+        }
+        if parsed.multiple {
+            return Err("quizzes cannot allow multiple answers".to_string());
+        }
+    }
+    if parsed.explanation.is_some() && parsed.correct.is_none() {
+        return Err("--explanation only applies to quizzes".to_string());
+    }
+    Ok(parsed)
+}
+
+/// `90s`, `30m`, `12h` or `7d`, in seconds.
+fn parse_duration(s: &str) -> Option<i64> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let n: i64 = s[..split].parse().ok()?;
+    let unit = match &s[split..] {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return None,
+    };
+    Some(n * unit).filter(|secs| *secs > 0)
+}
+
+/// `/poll` creates polls and quizzes, reports on them and stops them; results
+/// are kept in `store` as votes arrive.
+pub fn register(disp: &mut Dispatcher, admin: Option<i64>, store: PollStore) {
+    let store_cmd = store.clone();
+    let spec = CommandSpec::new("poll", "create a poll, or show results of or stop the latest one")
+        .usage("\"question\" \"option\" \"option\"... [--quiz=N] [--anonymous] [--multiple] [--explanation=text] [--close=7d] | results | stop")
+        .admin();
+    disp.add_command(spec, move |client: Client, msg: Message| {
+        let store = store_cmd.clone();
+        async move {
+            let Some(from) = msg.from.as_ref().map(|u| u.id) else { return Ok(()) };
+            if admin != Some(from) {
+                client.send_message(msg.chat.id, "not allowed", None).await?;
+                return Ok(());
+            }
+            let text = msg.text_or_caption().unwrap_or_default();
+            let args = parse_args(text.split_once(char::is_whitespace).map(|(_, rest)| rest).unwrap_or(""));
+            match args.first().map(|a| a.as_str()) {
+                Some("results") | Some("stop") if args.len() == 1 => {
+                    // A reply to the poll picks it; otherwise the latest one in this chat.
+                    let target = match msg.reply_to_message.as_ref().filter(|r| r.poll.is_some()) {
+                        Some(r) => store.by_message(msg.chat.id, r.message_id).await,
+                        None => store.latest_in_chat(msg.chat.id).await,
+                    };
+                    let Some(record) = target else {
+                        client.send_message(msg.chat.id, "No poll found in this chat.", None).await?;
+                        return Ok(());
+                    };
+                    let record = if args[0] == "stop" && !record.closed { stop(&client, &store, &record).await? } else { record };
+                    client.send_message(msg.chat.id, &record.summary(), None).await?;
+                }
+                _ => match parse_poll_args(&args) {
+                    Ok(poll) => create(&client, &store, msg.chat.id, from, poll).await?,
+                    Err(e) => {
+                        client.send_message(msg.chat.id, &format!("{}\n\nusage: /poll \"question\" \"option\" \"option\"... [--quiz=N] [--anonymous] [--multiple] [--explanation=text] [--close=7d]", e), None).await?;
+                    }
+                },
+            }
+            Ok(())
+        }
+    });
+
+    let store_poll = store.clone();
+    disp.add_poll(move |_client: Client, poll: Poll| {
+        let store = store_poll.clone();
+        async move {
+            store.apply_poll(&poll).await?;
+            Ok(())
+        }
+    });
+
+    disp.add_poll_answer(move |_client: Client, answer: PollAnswer| {
+        let store = store.clone();
+        async move {
+            store.apply_answer(&answer).await?;
+            Ok(())
+        }
+    });
+}
+
+async fn create(client: &Client, store: &PollStore, chat_id: i64, from: i64, args: PollArgs) -> Result<(), BotError> {
+    let now = Utc::now().timestamp();
+    let mut req = match args.correct {
+        Some(correct) => SendPoll::quiz(chat_id, args.question.clone(), args.options.clone(), correct),
+        None => SendPoll::new(chat_id, args.question.clone(), args.options.clone()).allows_multiple_answers(args.multiple),
+    };
+    req = req.anonymous(args.anonymous);
+    if let Some(explanation) = args.explanation {
+        req = req.explanation(explanation);
+    }
+    let mut closes_at = None;
+    match args.close_after {
+        Some(secs) if secs <= MAX_CLOSE_DATE_SECS => req = req.close_date(now + secs.max(5)),
+        Some(secs) => closes_at = Some(now + secs),
+        None => {}
+    }
+
+    let sent = client.send_poll(req).await?;
+    let Some(poll) = sent.poll else { return Err(BotError::Api("sendPoll returned no poll".into())) };
+    store
+        .insert(PollRecord {
+            poll_id: poll.id.clone(),
+            chat_id: sent.chat.id,
+            message_id: sent.message_id,
+            question: args.question,
+            options: args.options,
+            quiz: args.correct.is_some(),
+            correct_option_id: args.correct,
+            anonymous: args.anonymous,
+            created_by: from,
+            created_at: now,
+            closes_at,
+            closed: false,
+            counts: vec![0; poll.options.len()],
+            total_voters: 0,
+            votes: BTreeMap::new(),
+        })
+        .await?;
+    Ok(())
+}
+
+async fn stop(client: &Client, store: &PollStore, record: &PollRecord) -> Result<PollRecord, BotError> {
+    match client.stop_poll(record.chat_id, record.message_id).await {
+        Ok(poll) => Ok(store.apply_poll(&poll).await?.unwrap_or_else(|| record.clone())),
+        // Closed already, by Telegram's close_date or by hand in the chat.
+        Err(e) if e.error_code() == Some(400) && e.description().is_some_and(|d| d.contains("poll has already been closed")) => {
+            store.mark_closed(&record.poll_id).await?;
+            Ok(PollRecord { closed: true, ..record.clone() })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Stops the polls whose `--close` time has passed and posts their results.
+/// A poll Telegram refuses to stop for good, e.g. because the message was
+/// deleted or the bot left the chat, is given up on; after network errors,
+/// 429s and 5xx responses it is tried again on the next call.
+pub async fn close_due(client: &Client, store: &PollStore) {
+    for record in store.due(Utc::now().timestamp()).await {
+        let closed = match stop(client, store, &record).await {
+            Ok(closed) => closed,
+            Err(e) if matches!(e.error_code(), Some(400) | Some(403)) => {
+                tracing::warn!("giving up on poll {} in chat {}: {}", record.poll_id, record.chat_id, e);
+                if let Err(e) = store.mark_closed(&record.poll_id).await {
+                    tracing::warn!("failed to mark poll {} closed: {}", record.poll_id, e);
+                }
+                continue;
+            }
+            Err(e) => {
+                tracing::warn!("failed to stop poll {} in chat {}, will retry: {}", record.poll_id, record.chat_id, e);
+                continue;
+            }
+        };
+        if let Err(e) = client.send_message(closed.chat_id, &closed.summary(), None).await {
+            tracing::warn!("failed to post results of poll {} in chat {}: {}", record.poll_id, record.chat_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{poll, MockBotApi, Reply, TempDir};
+    use serde_json::json;
+    use tokio::time::{timeout, Duration};
+
+    const ADMIN: i64 = 1;
+    const GROUP: i64 = -100;
+    /// How long to wait for handlers to update the store.
+    const WAIT: Duration = Duration::from_secs(10);
+
+    fn args(s: &str) -> Result<PollArgs, String> {
+        parse_poll_args(&parse_args(s))
+    }
+
+    #[test]
+    fn parses_quoted_poll_arguments() {
+        let p = args(r#""Where to eat?" "Pizza place" Sushi --multiple --close=7d"#).unwrap();
+        assert_eq!(p.question, "Where to eat?");
+        assert_eq!(p.options, vec!["Pizza place", "Sushi"]);
+        assert!(p.multiple && !p.anonymous);
+        assert_eq!(p.close_after, Some(7 * 86400));
+
+        let q = args(r#""2 + 2?" 3 4 5 --quiz=2 --anonymous "--explanation=basic math""#).unwrap();
+        assert_eq!(q.correct, Some(1));
+        assert_eq!(q.explanation.as_deref(), Some("basic math"));
+
+        assert!(args(r#""Only one?" yes"#).is_err());
+        assert!(args(r#""Q" a b --quiz=3"#).is_err());
+        assert!(args(r#""Q" a b --quiz=1 --multiple"#).is_err());
+        assert!(args(r#""Q" a b --close=soon"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn creates_tracks_and_stops_polls() {
+        let dir = TempDir::new("poll-cmd");
+        let path = dir.path().join("polls.json");
+        let store = PollStore::open(&path).await.unwrap();
+        let mut disp = Dispatcher::new();
+        register(&mut disp, Some(ADMIN), store.clone());
+        let api = MockBotApi::start().await;
+        let client = api.client();
+
+        api.push_message(GROUP, 7, r#"/poll "Lunch?" Pizza Sushi"#);
+        api.push_message(GROUP, ADMIN, r#"/poll "Lunch?" "Pizza place" Sushi --close=2d"#);
+        poll(&client, &disp).await;
+        let denied = api.wait_for("sendMessage", 1).await;
+        assert_eq!(denied[0].str("text"), Some("not allowed"));
+        let sent = &api.wait_for("sendPoll", 1).await[0];
+        assert_eq!(sent.str("question"), Some("Lunch?"));
+        assert_eq!(sent.params["options"], json!([{ "text": "Pizza place" }, { "text": "Sushi" }]));
+        assert_eq!(sent.params["is_anonymous"], false);
+        assert!(sent.params.get("close_date").is_none());
+
+        let record = timeout(WAIT, async {
+            loop {
+                if let Some(r) = store.latest_in_chat(GROUP).await {
+                    break r;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the poll was never recorded");
+        assert!(record.closes_at.is_some());
+        api.push_poll_answer(&record.poll_id, 7, &[1]);
+        api.push_update(json!({ "poll": {
+            "id": record.poll_id, "question": "Lunch?", "total_voter_count": 1, "is_closed": false, "is_anonymous": false,
+            "type": "regular", "allows_multiple_answers": false,
+            "options": [{ "text": "Pizza place", "voter_count": 0 }, { "text": "Sushi", "voter_count": 1 }],
+        } }));
+        poll(&client, &disp).await;
+        let voted = timeout(WAIT, async {
+            loop {
+                let r = store.latest_in_chat(GROUP).await.unwrap();
+                if r.total_voters == 1 && !r.votes.is_empty() {
+                    break r;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the vote was never recorded");
+        assert!(voted.summary().contains("Sushi: 1 (100%) — User 7"), "{}", voted.summary());
+
+        api.push_message(GROUP, ADMIN, "/poll results");
+        poll(&client, &disp).await;
+        let sent = api.wait_for("sendMessage", 2).await;
+        assert!(sent[1].str("text").unwrap().starts_with("Lunch?\n"));
+
+        api.push_message(GROUP, ADMIN, "/poll stop");
+        poll(&client, &disp).await;
+        let stopped = &api.wait_for("stopPoll", 1).await[0];
+        assert_eq!(stopped.i64("message_id"), Some(record.message_id));
+        let sent = api.wait_for("sendMessage", 3).await;
+        let summary = sent[2].str("text").unwrap();
+        assert!(summary.starts_with("Lunch? (closed)"), "{}", summary);
+        assert!(store.due(i64::MAX).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn closes_due_polls_past_failures() {
+        let dir = TempDir::new("poll-due");
+        let path = dir.path().join("polls.json");
+        let store = PollStore::open(&path).await.unwrap();
+        for (i, id) in ["p1", "p2", "p3", "p4"].into_iter().enumerate() {
+            store
+                .insert(PollRecord {
+                    poll_id: id.into(),
+                    chat_id: GROUP,
+                    message_id: i as i64 + 1,
+                    question: "Lunch?".into(),
+                    options: vec!["Pizza".into(), "Sushi".into()],
+                    quiz: false,
+                    correct_option_id: None,
+                    anonymous: false,
+                    created_by: ADMIN,
+                    created_at: 0,
+                    closes_at: Some(0),
+                    closed: false,
+                    counts: vec![0, 0],
+                    total_voters: 0,
+                    votes: BTreeMap::new(),
+                })
+                .await
+                .unwrap();
+        }
+        let api = MockBotApi::start().await;
+        api.enqueue("stopPoll", Reply::error(400, "Bad Request: poll has already been closed"));
+        api.enqueue("stopPoll", Reply::error(400, "Bad Request: message with poll to stop not found"));
+        api.enqueue("stopPoll", Reply::ok(json!({
+            "id": "p3", "question": "Lunch?", "total_voter_count": 2, "is_closed": true, "is_anonymous": false,
+            "type": "regular", "allows_multiple_answers": false,
+            "options": [{ "text": "Pizza", "voter_count": 2 }, { "text": "Sushi", "voter_count": 0 }],
+        })));
+        api.enqueue("stopPoll", Reply::error(403, "Forbidden: bot was kicked from the supergroup chat"));
+
+        close_due(&api.client(), &store).await;
+        assert_eq!(api.calls_to("stopPoll").len(), 4);
+        // Results go out for the polls that were closed; the deleted one and the
+        // one in a chat the bot left are given up on without them.
+        let sent = api.calls_to("sendMessage");
+        assert_eq!(sent.len(), 2);
+        assert!(sent[1].str("text").unwrap().contains("Pizza: 2 (100%)"));
+        assert!(store.due(i64::MAX).await.is_empty());
+        close_due(&api.client(), &store).await;
+        assert_eq!(api.calls_to("stopPoll").len(), 4);
+    }
+}
@@ -1,24 +1,39 @@
+use crate::client::requests::AnswerInlineQuery;
 use crate::client::Client;
-use crate::dispatch::Dispatcher;
-use crate::types::Message;
+use crate::dispatch::{CommandSpec, Dispatcher};
+use crate::types::{ChosenInlineResult, InlineQuery, InlineQueryResult, InlineQueryResultArticle, InputMessageContent, Message};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
 
 type KvStore = Arc<RwLock<HashMap<String, String>>>;
+type KvOwners = Arc<RwLock<HashMap<String, i64>>>;
 
-pub fn register(disp: &mut Dispatcher, kv: KvStore) {
+const INLINE_PAGE_SIZE: usize = 20;
+// Values change with every /set, so Telegram should not hold on to answers for long.
+const INLINE_CACHE_SECS: u32 = 10;
+
+/// `owners` records who last set each key, so inline search only offers users
+/// their own values.
+pub fn register(disp: &mut Dispatcher, kv: KvStore, owners: KvOwners) {
     let kv_set = kv.clone();
-    disp.add_command("set", move |_client: Client, msg: Message| {
+    let owners_set = owners.clone();
+    disp.add_command(CommandSpec::new("set", "save key/value (persisted)").arg("k").arg("v"), move |_client: Client, msg: Message| {
         let kv = kv_set.clone();
+        let owners = owners_set.clone();
         async move {
-            if let Some(text) = msg.text {
+            if let Some(text) = msg.text_or_caption() {
                 let mut parts = text.splitn(3, ' ');
                 parts.next();
                 if let Some(k) = parts.next() {
                     if let Some(v) = parts.next() {
                         let mut map = kv.write().await;
                         map.insert(k.to_string(), v.to_string());
+                        let mut owners = owners.write().await;
+                        match &msg.from {
+                            Some(user) => owners.insert(k.to_string(), user.id),
+                            None => owners.remove(k),
+                        };
                     }
                 }
             }
@@ -27,10 +42,10 @@ pub fn register(disp: &mut Dispatcher, kv: KvStore) {
     });
 
     let kv_get = kv.clone();
-    disp.add_command("get", move |client: Client, msg: Message| {
+    disp.add_command(CommandSpec::new("get", "get saved value").arg("k"), move |client: Client, msg: Message| {
         let kv = kv_get.clone();
         async move {
-            if let Some(text) = msg.text {
+            if let Some(text) = msg.text_or_caption() {
                 let mut parts = text.splitn(2, ' ');
                 parts.next();
                 if let Some(k) = parts.next() {
@@ -41,4 +56,40 @@ pub fn register(disp: &mut Dispatcher, kv: KvStore) {
             Ok(())
         }
     });
+
+    // `@bot query` in any chat lists the caller's saved values whose key or value
+    // contains the query; picking one sends the value.
+    let kv_inline = kv.clone();
+    disp.add_inline_query(move |client: Client, query: InlineQuery| {
+        let kv = kv_inline.clone();
+        let owners = owners.clone();
+        async move {
+            let needle = query.query.trim().to_lowercase();
+            let owners = owners.read().await;
+            let mut matches: Vec<(String, String)> = kv
+                .read()
+                .await
+                .iter()
+                .filter(|(k, _)| owners.get(*k) == Some(&query.from.id))
+                .filter(|(k, v)| k.to_lowercase().contains(&needle) || v.to_lowercase().contains(&needle))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            matches.sort();
+            let results = matches
+                .into_iter()
+                .enumerate()
+                .map(|(i, (k, v))| InlineQueryResultArticle::new(i.to_string(), k, InputMessageContent::text(v.clone())).description(v).into())
+                .collect::<Vec<InlineQueryResult>>();
+            drop(owners);
+            let answer = AnswerInlineQuery::page(&query, results, INLINE_PAGE_SIZE).cache_time(INLINE_CACHE_SECS).personal(true);
+            client.answer_inline_query(answer).await?;
+            Ok(())
+        }
+    });
+
+    // Only delivered when inline feedback is enabled with @BotFather.
+    disp.add_chosen_inline_result(|_client: Client, chosen: ChosenInlineResult| async move {
+        tracing::info!("inline result {} for {:?} shared by {}", chosen.result_id, chosen.query, chosen.from.id);
+        Ok(())
+    });
 }
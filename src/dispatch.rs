@@ -1,25 +1,69 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use crate::client::{Client, BotError};
-use crate::types::{Message, CallbackQuery};
+use crate::types::{
+    CallbackQuery, ChatJoinRequest, ChatMemberUpdated, ChosenInlineResult, InlineQuery, Message, MessageReactionUpdated, Poll, PollAnswer,
+    PreCheckoutQuery, ShippingQuery, Update, UpdateKind,
+};
+use crate::utils::parse_command;
 use tracing::error;
 use tokio::sync::Semaphore;
+use std::time::Instant;
+use crate::metrics::{self, Outcome};
+
+pub mod registry;
+
+pub use registry::{CommandRegistry, CommandSpec, Visibility};
+
+fn outcome<T>(result: &Result<Result<(), BotError>, T>) -> Outcome {
+    match result {
+        Ok(Ok(())) => Outcome::Ok,
+        Ok(Err(_)) => Outcome::Error,
+        Err(_) => Outcome::Panic,
+    }
+}
+
+/// Counts a spawned handler until it is dropped at the end of its task.
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn start(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter.clone())
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
 
 pub type Handler = Arc<dyn Fn(Client, Message) -> BoxFuture<'static, Result<(), BotError>> + Send + Sync>;
 pub type CallbackHandler = Arc<dyn Fn(Client, CallbackQuery) -> BoxFuture<'static, Result<(), BotError>> + Send + Sync>;
+/// A handler for one update kind; `None` when the update is of another kind.
+pub type UpdateHandler = Arc<dyn Fn(Client, &Update) -> Option<BoxFuture<'static, Result<(), BotError>>> + Send + Sync>;
 
 pub struct Dispatcher {
     commands: HashMap<String, Vec<Handler>>,
     callbacks: Vec<CallbackHandler>,
+    handlers: HashMap<UpdateKind, Vec<UpdateHandler>>,
+    registry: CommandRegistry,
     handler_sem: Option<Arc<Semaphore>>,
     admin: Option<i64>,
+    in_flight: Arc<AtomicUsize>,
 }
 
 impl Dispatcher {
     pub fn new() -> Self {
-        Self { commands: HashMap::new(), callbacks: Vec::new(), handler_sem: None, admin: None }
+        Self { commands: HashMap::new(), callbacks: Vec::new(), handlers: HashMap::new(), registry: CommandRegistry::default(), handler_sem: None, admin: None, in_flight: Arc::default() }
+    }
+
+    pub fn registry(&self) -> CommandRegistry {
+        self.registry.clone()
     }
 
     pub fn set_concurrency_limit(&mut self, sem: Arc<Semaphore>) {
@@ -30,7 +74,15 @@ impl Dispatcher {
         self.admin = admin;
     }
 
-    pub fn add_command<F, Fut>(&mut self, cmd: &str, f: F)
+    /// Number of spawned handlers that have not finished yet, including those
+    /// still waiting for a concurrency permit.
+    pub fn in_flight(&self) -> Arc<AtomicUsize> {
+        self.in_flight.clone()
+    }
+
+    /// Registers a handler for a command given either as a bare name or as a
+    /// `CommandSpec` carrying its help and menu metadata.
+    pub fn add_command<F, Fut>(&mut self, cmd: impl Into<CommandSpec>, f: F)
     where
         F: Fn(Client, Message) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = Result<(), BotError>> + Send + 'static,
@@ -38,7 +90,9 @@ impl Dispatcher {
         let h: Handler = Arc::new(move |client: Client, msg: Message| {
             (f)(client, msg).boxed()
         });
-        let key = cmd.trim_start_matches('/').to_string();
+        let spec = cmd.into();
+        let key = spec.name.clone();
+        self.registry.insert(spec);
         self.commands.entry(key).or_default().push(h);
     }
 
@@ -54,43 +108,13 @@ impl Dispatcher {
     }
 
     pub async fn dispatch(&self, client: Client, msg: Message) {
-        if let Some(text) = &msg.text {
-            if text.starts_with('/') {
-                let parts: Vec<&str> = text.split_whitespace().collect();
-                let cmd = parts[0].trim_start_matches('/').to_string();
+        if let Some(text) = msg.text_or_caption() {
+            if let Some(cmd) = parse_command(text, client.bot_username()) {
                 if let Some(handlers) = self.commands.get(&cmd) {
+                    metrics::get().command_invoked(&cmd);
                     for h in handlers {
-                        let c_for_call = client.clone();
-                        let c_for_notify = c_for_call.clone();
-                        let m = msg.clone();
-                        let cmd_clone = cmd.clone();
-
-                        let sem = self.handler_sem.clone();
-                        let admin = self.admin;
-                        let fut = h(c_for_call, m);
-                        tokio::spawn(async move {
-                            let _permit = if let Some(s) = sem {
-                                match s.clone().acquire_owned().await {
-                                    Ok(p) => Some(p),
-                                    Err(_) => None,
-                                }
-                            } else { None };
-                            match std::panic::AssertUnwindSafe(fut).catch_unwind().await {
-                                Ok(Ok(())) => {}
-                                Ok(Err(e)) => {
-                                    error!("handler error: {}", e);
-                                    if let Some(aid) = admin {
-                                        let _ = c_for_notify.send_message(aid, &format!("Handler error for command '/{}': {}", cmd_clone, e), None).await;
-                                    }
-                                }
-                                Err(p) => {
-                                    error!("handler panicked: {:?}", p);
-                                    if let Some(aid) = admin {
-                                        let _ = c_for_notify.send_message(aid, &format!("Handler panicked for command '/{}': {:?}", cmd_clone, p), None).await;
-                                    }
-                                }
-                            }
-                        });
+                        let fut = h(client.clone(), msg.clone());
+                        self.spawn_handler(client.clone(), cmd.clone(), format!("command '/{}'", cmd), fut);
                     }
                 }
             }
@@ -99,35 +123,221 @@ impl Dispatcher {
 
     pub async fn dispatch_callback(&self, client: Client, cb: CallbackQuery) {
         for h in &self.callbacks {
-            let c_for_call = client.clone();
-            let c_for_notify = c_for_call.clone();
-            let cb_clone = cb.clone();
-            let sem = self.handler_sem.clone();
-            let admin = self.admin;
-            let fut = h(c_for_call, cb_clone);
-            tokio::spawn(async move {
-                let _permit = if let Some(s) = sem {
-                    match s.clone().acquire_owned().await {
-                        Ok(p) => Some(p),
-                        Err(_) => None,
-                    }
-                } else { None };
-                match std::panic::AssertUnwindSafe(fut).catch_unwind().await {
-                    Ok(Ok(())) => {}
-                    Ok(Err(e)) => {
-                        error!("callback handler error: {}", e);
-                        if let Some(aid) = admin {
-                            let _ = c_for_notify.send_message(aid, &format!("Callback handler error: {}", e), None).await;
-                        }
+            let fut = h(client.clone(), cb.clone());
+            self.spawn_handler(client.clone(), "callback".to_string(), "callback query".to_string(), fut);
+        }
+    }
+
+    /// Routes an update of any kind: messages go through command dispatch,
+    /// callback queries to the callback handlers, and every kind to the handlers
+    /// registered for it with the `add_<kind>` methods.
+    pub async fn dispatch_update(&self, client: Client, update: Update) {
+        crate::cassette::for_update(update.update_id, self.route_update(client, update)).await
+    }
+
+    async fn route_update(&self, client: Client, update: Update) {
+        let Some(kind) = update.kind() else { return };
+        if let Some(msg) = &update.message {
+            self.dispatch(client.clone(), msg.clone()).await;
+        }
+        if let Some(cb) = &update.callback_query {
+            self.dispatch_callback(client.clone(), cb.clone()).await;
+        }
+        for h in self.handlers.get(&kind).into_iter().flatten() {
+            if let Some(fut) = h(client.clone(), &update) {
+                self.spawn_handler(client.clone(), kind.as_str().to_string(), kind.as_str().to_string(), fut);
+            }
+        }
+    }
+
+    /// The kinds to request in `allowed_updates`: messages, which commands need,
+    /// plus every kind that has a handler.
+    pub fn allowed_updates(&self) -> Vec<UpdateKind> {
+        UpdateKind::ALL
+            .into_iter()
+            .filter(|k| match k {
+                UpdateKind::Message => true,
+                UpdateKind::CallbackQuery => !self.callbacks.is_empty() || self.handlers.contains_key(k),
+                _ => self.handlers.contains_key(k),
+            })
+            .collect()
+    }
+
+    /// Runs a handler on its own task under the concurrency limit. Errors and
+    /// panics are logged, counted and reported to the admin; `what` names the
+    /// handler in those reports and `name` in the metrics.
+    fn spawn_handler(&self, client: Client, name: String, what: String, fut: BoxFuture<'static, Result<(), BotError>>) {
+        let sem = self.handler_sem.clone();
+        let admin = self.admin;
+        let in_flight = InFlight::start(&self.in_flight);
+        let update_id = crate::cassette::current_update();
+        let task = async move {
+            let _in_flight = in_flight;
+            let _permit = if let Some(s) = sem {
+                s.clone().acquire_owned().await.ok()
+            } else { None };
+            let started = Instant::now();
+            let result = std::panic::AssertUnwindSafe(fut).catch_unwind().await;
+            metrics::get().handler_finished(&name, started.elapsed(), outcome(&result));
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    error!("handler error for {}: {}", what, e);
+                    if let Some(aid) = admin {
+                        let _ = client.send_message(aid, &format!("Handler error for {}: {}", what, e), None).await;
                     }
-                    Err(p) => {
-                        error!("callback handler panicked: {:?}", p);
-                        if let Some(aid) = admin {
-                            let _ = c_for_notify.send_message(aid, &format!("Callback handler panicked: {:?}", p), None).await;
-                        }
+                }
+                Err(p) => {
+                    error!("handler panicked for {}: {:?}", what, p);
+                    if let Some(aid) = admin {
+                        let _ = client.send_message(aid, &format!("Handler panicked for {}: {:?}", what, p), None).await;
                     }
                 }
+            }
+        };
+        match update_id {
+            Some(id) => tokio::spawn(crate::cassette::for_update(id, task)),
+            None => tokio::spawn(task),
+        };
+    }
+
+    fn on<T, F, Fut>(&mut self, kind: UpdateKind, extract: fn(&Update) -> Option<&T>, f: F)
+    where
+        T: Clone + Send + 'static,
+        F: Fn(Client, T) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<(), BotError>> + Send + 'static,
+    {
+        let h: UpdateHandler = Arc::new(move |client: Client, update: &Update| {
+            extract(update).map(|v| (f)(client, v.clone()).boxed())
+        });
+        self.handlers.entry(kind).or_default().push(h);
+    }
+}
+
+macro_rules! update_handlers {
+    ($($(#[$doc:meta])* $name:ident => $kind:ident, $field:ident: $ty:ty;)*) => {
+        $(
+            $(#[$doc])*
+            pub fn $name<F, Fut>(&mut self, f: F)
+            where
+                F: Fn(Client, $ty) -> Fut + Send + Sync + 'static,
+                Fut: std::future::Future<Output = Result<(), BotError>> + Send + 'static,
+            {
+                self.on(UpdateKind::$kind, |u| u.$field.as_ref(), f)
+            }
+        )*
+    };
+}
+
+#[allow(dead_code)]
+impl Dispatcher {
+    update_handlers! {
+        /// Runs for every message, commands included, after command dispatch.
+        add_message => Message, message: Message;
+        add_edited_message => EditedMessage, edited_message: Message;
+        add_channel_post => ChannelPost, channel_post: Message;
+        add_edited_channel_post => EditedChannelPost, edited_channel_post: Message;
+        add_inline_query => InlineQuery, inline_query: InlineQuery;
+        add_chosen_inline_result => ChosenInlineResult, chosen_inline_result: ChosenInlineResult;
+        add_shipping_query => ShippingQuery, shipping_query: ShippingQuery;
+        add_pre_checkout_query => PreCheckoutQuery, pre_checkout_query: PreCheckoutQuery;
+        add_poll => Poll, poll: Poll;
+        add_poll_answer => PollAnswer, poll_answer: PollAnswer;
+        /// Changes to the bot's own membership, e.g. being blocked or added to a group.
+        add_my_chat_member => MyChatMember, my_chat_member: ChatMemberUpdated;
+        /// Other members' changes; Telegram only sends these when requested in `allowed_updates`.
+        add_chat_member => ChatMember, chat_member: ChatMemberUpdated;
+        add_chat_join_request => ChatJoinRequest, chat_join_request: ChatJoinRequest;
+        add_message_reaction => MessageReaction, message_reaction: MessageReactionUpdated;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockBotApi;
+    use crate::types::{Extra, ReactionType};
+    use serde_json::json;
+
+    fn user(id: i64) -> serde_json::Value {
+        json!({ "id": id, "is_bot": false, "first_name": "U" })
+    }
+
+    #[tokio::test]
+    async fn routes_each_kind_to_its_handler() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let mut disp = Dispatcher::new();
+        disp.add_poll_answer(|client: Client, answer: PollAnswer| async move {
+            client.send_message(1, &format!("vote {:?}", answer.option_ids), None).await?;
+            Ok(())
+        });
+        disp.add_my_chat_member(|client: Client, upd: ChatMemberUpdated| async move {
+            client.send_message(1, &format!("status {}", upd.new_chat_member.status), None).await?;
+            Ok(())
+        });
+        disp.add_message_reaction(|client: Client, upd: MessageReactionUpdated| async move {
+            assert_eq!(upd.new_reaction, vec![ReactionType::Emoji { emoji: "👍".into(), extra: Extra::new() }, ReactionType::Unknown(json!({ "type": "something_new" }))]);
+            client.send_message(1, "reaction", None).await?;
+            Ok(())
+        });
+
+        api.push_update(json!({ "poll_answer": { "poll_id": "p1", "user": user(7), "option_ids": [0, 2] } }));
+        api.push_update(json!({ "my_chat_member": {
+            "chat": { "id": 7, "type": "private" },
+            "from": user(7),
+            "date": 0,
+            "old_chat_member": { "status": "member", "user": user(1) },
+            "new_chat_member": { "status": "kicked", "user": user(1), "until_date": 0 },
+        } }));
+        api.push_update(json!({ "message_reaction": {
+            "chat": { "id": -5, "type": "supergroup" },
+            "message_id": 3,
+            "user": user(7),
+            "date": 0,
+            "old_reaction": [],
+            "new_reaction": [{ "type": "emoji", "emoji": "👍" }, { "type": "something_new" }],
+        } }));
+
+        let updates = client.get_updates(0, 0, &disp.allowed_updates()).await.unwrap();
+        let kinds: Vec<_> = updates.iter().map(|u| u.kind()).collect();
+        assert_eq!(kinds, vec![Some(UpdateKind::PollAnswer), Some(UpdateKind::MyChatMember), Some(UpdateKind::MessageReaction)]);
+        for u in updates {
+            disp.dispatch_update(client.clone(), u).await;
+        }
+
+        let mut texts: Vec<_> = api.wait_for("sendMessage", 3).await.iter().map(|c| c.str("text").unwrap().to_string()).collect();
+        texts.sort();
+        assert_eq!(texts, vec!["reaction", "status kicked", "vote [0, 2]"]);
+    }
+
+    #[tokio::test]
+    async fn counts_a_command_once_per_message() {
+        let api = MockBotApi::start().await;
+        let client = api.client();
+        let mut disp = Dispatcher::new();
+        for reply in ["one", "two"] {
+            disp.add_command("counted_once", move |client: Client, msg: Message| async move {
+                client.send_message(msg.chat.id, reply, None).await?;
+                Ok(())
             });
         }
+        api.push_message(42, 42, "/counted_once");
+        crate::testing::poll(&client, &disp).await;
+
+        api.wait_for("sendMessage", 2).await;
+        assert!(metrics::get().render().contains("bot_commands_total{command=\"counted_once\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn allowed_updates_follow_registered_handlers() {
+        let mut disp = Dispatcher::new();
+        assert_eq!(disp.allowed_updates(), vec![UpdateKind::Message]);
+
+        disp.add_callback(|_: Client, _: CallbackQuery| async { Ok(()) });
+        disp.add_chat_member(|_: Client, _: ChatMemberUpdated| async { Ok(()) });
+        assert_eq!(disp.allowed_updates(), vec![UpdateKind::Message, UpdateKind::CallbackQuery, UpdateKind::ChatMember]);
+        assert_eq!(serde_json::to_value(disp.allowed_updates()).unwrap(), json!(["message", "callback_query", "chat_member"]));
+        assert_eq!("chat_join_request".parse::<UpdateKind>(), Ok(UpdateKind::ChatJoinRequest));
     }
 }
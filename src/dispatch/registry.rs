@@ -0,0 +1,288 @@
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+use crate::client::{BotError, Client};
+use crate::types::{BotCommand, BotCommandScope};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Admin,
+}
+
+#[derive(Clone, Debug)]
+pub struct ArgSpec {
+    pub name: String,
+    pub required: bool,
+}
+
+/// Metadata for a command: what `/help` shows and what is pushed to the Telegram
+/// command menu. A spec without a description is dispatched but never listed.
+#[derive(Clone, Debug)]
+pub struct CommandSpec {
+    pub name: String,
+    pub description: String,
+    pub usage: Option<String>,
+    pub args: Vec<ArgSpec>,
+    pub visibility: Visibility,
+    pub scopes: Vec<BotCommandScope>,
+    pub translations: Vec<(String, String)>,
+}
+
+impl From<&str> for CommandSpec {
+    fn from(name: &str) -> Self {
+        CommandSpec::new(name, "")
+    }
+}
+
+#[allow(dead_code)]
+impl CommandSpec {
+    pub fn new(name: &str, description: &str) -> Self {
+        Self {
+            name: name.trim_start_matches('/').to_string(),
+            description: description.to_string(),
+            usage: None,
+            args: Vec::new(),
+            visibility: Visibility::Public,
+            scopes: Vec::new(),
+            translations: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, name: &str) -> Self {
+        self.args.push(ArgSpec { name: name.to_string(), required: true });
+        self
+    }
+
+    pub fn optional_arg(mut self, name: &str) -> Self {
+        self.args.push(ArgSpec { name: name.to_string(), required: false });
+        self
+    }
+
+    /// Overrides the usage string that is otherwise built from the arguments.
+    pub fn usage(mut self, usage: &str) -> Self {
+        self.usage = Some(usage.to_string());
+        self
+    }
+
+    pub fn admin(mut self) -> Self {
+        self.visibility = Visibility::Admin;
+        self
+    }
+
+    /// Limits where the command shows up in the menu. Without scopes it is listed
+    /// in the default scope (or the admin's chat for admin commands).
+    pub fn scope(mut self, scope: BotCommandScope) -> Self {
+        self.scopes.push(scope);
+        self
+    }
+
+    /// Adds a menu description for users with the given IETF language code.
+    pub fn description_for(mut self, language_code: &str, description: &str) -> Self {
+        self.translations.push((language_code.to_string(), description.to_string()));
+        self
+    }
+
+    pub fn usage_line(&self) -> String {
+        let usage = self.usage.clone().unwrap_or_else(|| {
+            self.args
+                .iter()
+                .map(|a| if a.required { format!("<{}>", a.name) } else { format!("[{}]", a.name) })
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+        if usage.is_empty() { format!("/{}", self.name) } else { format!("/{} {}", self.name, usage) }
+    }
+
+    pub fn is_listed(&self) -> bool {
+        !self.description.is_empty()
+    }
+
+    fn description_in(&self, language_code: Option<&str>) -> &str {
+        language_code
+            .and_then(|l| self.translations.iter().find(|(code, _)| code == l))
+            .map(|(_, d)| d.as_str())
+            .unwrap_or(&self.description)
+    }
+
+    // Telegram accepts 1-32 lowercase letters, digits and underscores.
+    fn is_valid_menu_command(&self) -> bool {
+        !self.name.is_empty()
+            && self.name.len() <= 32
+            && self.name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    }
+}
+
+/// Shared list of registered commands. Handlers such as `/help` hold a clone and
+/// see every command registered on the dispatcher, including later ones.
+#[derive(Clone, Default)]
+pub struct CommandRegistry {
+    specs: Arc<RwLock<Vec<CommandSpec>>>,
+}
+
+impl CommandRegistry {
+    pub fn insert(&self, spec: CommandSpec) {
+        if let Ok(mut specs) = self.specs.write() {
+            // A second handler registered by bare name keeps the existing metadata.
+            match specs.iter().position(|s| s.name == spec.name) {
+                Some(i) if spec.is_listed() => specs[i] = spec,
+                Some(_) => {}
+                None => specs.push(spec),
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<CommandSpec> {
+        let name = name.trim_start_matches('/');
+        self.specs.read().ok().and_then(|s| s.iter().find(|c| c.name == name).cloned())
+    }
+
+    /// Listed commands in registration order; admin commands only when `include_admin`.
+    pub fn listed(&self, include_admin: bool) -> Vec<CommandSpec> {
+        self.specs
+            .read()
+            .map(|s| {
+                s.iter()
+                    .filter(|c| c.is_listed() && (include_admin || c.visibility == Visibility::Public))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn scoped(&self, admin: Option<i64>) -> Vec<(BotCommandScope, Vec<CommandSpec>)> {
+        let mut out: Vec<(BotCommandScope, Vec<CommandSpec>)> = Vec::new();
+        let mut add = |scope: BotCommandScope, spec: &CommandSpec| match out.iter_mut().find(|(s, _)| *s == scope) {
+            Some((_, specs)) => specs.push(spec.clone()),
+            None => out.push((scope, vec![spec.clone()])),
+        };
+        for spec in self.listed(true).iter().filter(|s| s.is_valid_menu_command()) {
+            match (spec.visibility, admin) {
+                (Visibility::Admin, Some(aid)) => add(BotCommandScope::Chat { chat_id: aid }, spec),
+                (Visibility::Admin, None) => {}
+                (Visibility::Public, _) if spec.scopes.is_empty() => {
+                    add(BotCommandScope::Default, spec);
+                    // A chat scope replaces the default list for that chat, so the
+                    // admin's menu has to repeat the public commands.
+                    if let Some(aid) = admin {
+                        add(BotCommandScope::Chat { chat_id: aid }, spec);
+                    }
+                }
+                (Visibility::Public, _) => {
+                    for scope in &spec.scopes {
+                        add(scope.clone(), spec);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Pushes the registered commands to Telegram with setMyCommands, once per
+    /// scope and once per language that has translated descriptions.
+    pub async fn sync(&self, client: &Client, admin: Option<i64>) -> Result<(), BotError> {
+        for (scope, specs) in self.scoped(admin) {
+            let mut languages: Vec<Option<String>> = vec![None];
+            for spec in &specs {
+                for (code, _) in &spec.translations {
+                    if !languages.iter().any(|l| l.as_deref() == Some(code.as_str())) {
+                        languages.push(Some(code.clone()));
+                    }
+                }
+            }
+            for lang in languages {
+                let commands: Vec<BotCommand> = specs
+                    .iter()
+                    .map(|s| BotCommand {
+                        command: s.name.clone(),
+                        description: s.description_in(lang.as_deref()).chars().take(256).collect(),
+                    })
+                    .collect();
+                if let Err(e) = client.set_my_commands(commands, Some(scope.clone()), lang.as_deref()).await {
+                    warn!("setMyCommands failed for {:?} ({:?}): {}", scope, lang, e);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockBotApi, Reply};
+    use serde_json::{json, Value};
+
+    const ADMIN: i64 = 1;
+
+    fn registry() -> CommandRegistry {
+        let r = CommandRegistry::default();
+        r.insert(CommandSpec::new("start", "start the bot").description_for("ru", "запустить бота"));
+        r.insert(CommandSpec::new("stats", "bot statistics").admin());
+        r.insert(CommandSpec::new("group", "group only").scope(BotCommandScope::AllGroupChats));
+        r.insert(CommandSpec::new("hidden", ""));
+        r.insert(CommandSpec::new("BadName", "not a valid menu command"));
+        r
+    }
+
+    fn names(specs: &[CommandSpec]) -> Vec<&str> {
+        specs.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn filters_by_visibility() {
+        let r = registry();
+        assert_eq!(names(&r.listed(false)), vec!["start", "group", "BadName"]);
+        assert_eq!(names(&r.listed(true)), vec!["start", "stats", "group", "BadName"]);
+        assert!(r.get("/hidden").is_some());
+
+        // Re-registering by bare name keeps the metadata.
+        r.insert(CommandSpec::from("stats"));
+        assert_eq!(r.get("stats").unwrap().visibility, Visibility::Admin);
+    }
+
+    #[test]
+    fn groups_commands_by_scope() {
+        let r = registry();
+        let scoped = r.scoped(Some(ADMIN));
+        let scoped: Vec<(BotCommandScope, Vec<&str>)> = scoped.iter().map(|(s, specs)| (s.clone(), names(specs))).collect();
+        assert_eq!(scoped, vec![
+            (BotCommandScope::Default, vec!["start"]),
+            (BotCommandScope::Chat { chat_id: ADMIN }, vec!["start", "stats"]),
+            (BotCommandScope::AllGroupChats, vec!["group"]),
+        ]);
+
+        let scoped: Vec<BotCommandScope> = r.scoped(None).into_iter().map(|(s, _)| s).collect();
+        assert_eq!(scoped, vec![BotCommandScope::Default, BotCommandScope::AllGroupChats]);
+    }
+
+    fn commands(call: &crate::testing::Call) -> Vec<&str> {
+        call.params["commands"].as_array().unwrap().iter().map(|c| c["command"].as_str().unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn syncs_each_scope_and_language() {
+        let api = MockBotApi::start().await;
+        registry().sync(&api.client(), Some(ADMIN)).await.unwrap();
+
+        let calls = api.calls_to("setMyCommands");
+        let sent: Vec<(Value, Option<&str>, Vec<&str>)> = calls.iter().map(|c| (c.params["scope"].clone(), c.str("language_code"), commands(c))).collect();
+        assert_eq!(sent, vec![
+            (json!({ "type": "default" }), None, vec!["start"]),
+            (json!({ "type": "default" }), Some("ru"), vec!["start"]),
+            (json!({ "type": "chat", "chat_id": ADMIN }), None, vec!["start", "stats"]),
+            (json!({ "type": "chat", "chat_id": ADMIN }), Some("ru"), vec!["start", "stats"]),
+            (json!({ "type": "all_group_chats" }), None, vec!["group"]),
+        ]);
+        assert_eq!(calls[1].params["commands"][0]["description"], "запустить бота");
+        assert_eq!(calls[3].params["commands"][1]["description"], "bot statistics");
+    }
+
+    #[tokio::test]
+    async fn sync_stops_at_the_first_error() {
+        let api = MockBotApi::start().await;
+        api.enqueue("setMyCommands", Reply::error(400, "Bad Request: BOT_COMMAND_INVALID"));
+        assert_eq!(registry().sync(&api.client(), None).await.unwrap_err().error_code(), Some(400));
+        assert_eq!(api.calls_to("setMyCommands").len(), 1);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::types::ParseMode;
+use thiserror::Error;
+
+/// Legacy Markdown has neither the escapes nor the entities MarkdownV2 has, so
+/// text is only rendered for HTML and MarkdownV2.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("legacy Markdown is not supported, use MarkdownV2 or HTML")]
+pub struct LegacyMarkdown;
+
+/// Escapes text for `parse_mode = HTML`.
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_with(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Escapes text for `parse_mode = MarkdownV2`.
+pub fn escape_markdown_v2(s: &str) -> String {
+    escape_with(s, &['\\', '_', '*', '[', ']', '(', ')', '~', '`', '>', '#', '+', '-', '=', '|', '{', '}', '.', '!'])
+}
+
+/// Escapes the content of a MarkdownV2 `code` or `pre` entity.
+pub fn escape_markdown_v2_code(s: &str) -> String {
+    escape_with(s, &['\\', '`'])
+}
+
+/// Escapes the URL part of a MarkdownV2 inline link.
+pub fn escape_markdown_v2_url(s: &str) -> String {
+    escape_with(s, &['\\', ')'])
+}
+
+/// Escapes text for the given parse mode.
+#[allow(dead_code)]
+pub fn escape(s: &str, mode: ParseMode) -> Result<String, LegacyMarkdown> {
+    match mode {
+        ParseMode::Html => Ok(escape_html(s)),
+        ParseMode::MarkdownV2 => Ok(escape_markdown_v2(s)),
+        ParseMode::Markdown => Err(LegacyMarkdown),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Plain(String),
+    Bold(Text),
+    Italic(Text),
+    Underline(Text),
+    Strike(Text),
+    Spoiler(Text),
+    Code(String),
+    Pre { code: String, language: Option<String> },
+    Link { url: String, text: Text },
+    Blockquote(Text),
+}
+
+/// Formatted message text. Every piece of plain text is escaped when the value is
+/// rendered, so user input can be embedded safely in either parse mode.
+#[derive(Debug, Clone, Default)]
+pub struct Text {
+    nodes: Vec<Node>,
+}
+
+impl From<&str> for Text {
+    fn from(s: &str) -> Self {
+        Text::new().text(s)
+    }
+}
+
+impl From<String> for Text {
+    fn from(s: String) -> Self {
+        Text::new().text(s)
+    }
+}
+
+#[allow(dead_code)]
+impl Text {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(mut self, node: Node) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    pub fn text(self, s: impl Into<String>) -> Self {
+        self.push(Node::Plain(s.into()))
+    }
+
+    pub fn line(self, s: impl Into<String>) -> Self {
+        let mut s = s.into();
+        s.push('\n');
+        self.text(s)
+    }
+
+    pub fn newline(self) -> Self {
+        self.text("\n")
+    }
+
+    pub fn bold(self, t: impl Into<Text>) -> Self {
+        self.push(Node::Bold(t.into()))
+    }
+
+    pub fn italic(self, t: impl Into<Text>) -> Self {
+        self.push(Node::Italic(t.into()))
+    }
+
+    pub fn underline(self, t: impl Into<Text>) -> Self {
+        self.push(Node::Underline(t.into()))
+    }
+
+    pub fn strike(self, t: impl Into<Text>) -> Self {
+        self.push(Node::Strike(t.into()))
+    }
+
+    pub fn spoiler(self, t: impl Into<Text>) -> Self {
+        self.push(Node::Spoiler(t.into()))
+    }
+
+    pub fn code(self, s: impl Into<String>) -> Self {
+        self.push(Node::Code(s.into()))
+    }
+
+    pub fn pre(self, code: impl Into<String>, language: Option<&str>) -> Self {
+        self.push(Node::Pre { code: code.into(), language: language.map(|l| l.to_string()) })
+    }
+
+    pub fn link(self, url: impl Into<String>, t: impl Into<Text>) -> Self {
+        self.push(Node::Link { url: url.into(), text: t.into() })
+    }
+
+    /// Mentions a user by id, which works even for users without a username.
+    pub fn mention(self, user_id: i64, t: impl Into<Text>) -> Self {
+        self.link(format!("tg://user?id={}", user_id), t)
+    }
+
+    pub fn blockquote(self, t: impl Into<Text>) -> Self {
+        self.push(Node::Blockquote(t.into()))
+    }
+
+    pub fn append(mut self, other: Text) -> Self {
+        self.nodes.extend(other.nodes);
+        self
+    }
+
+    pub fn render(&self, mode: ParseMode) -> Result<String, LegacyMarkdown> {
+        match mode {
+            ParseMode::Html => Ok(self.to_html()),
+            ParseMode::MarkdownV2 => Ok(self.to_markdown_v2()),
+            ParseMode::Markdown => Err(LegacyMarkdown),
+        }
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = String::new();
+        for n in &self.nodes {
+            match n {
+                Node::Plain(s) => out.push_str(&escape_html(s)),
+                Node::Bold(t) => out.push_str(&format!("<b>{}</b>", t.to_html())),
+                Node::Italic(t) => out.push_str(&format!("<i>{}</i>", t.to_html())),
+                Node::Underline(t) => out.push_str(&format!("<u>{}</u>", t.to_html())),
+                Node::Strike(t) => out.push_str(&format!("<s>{}</s>", t.to_html())),
+                Node::Spoiler(t) => out.push_str(&format!("<tg-spoiler>{}</tg-spoiler>", t.to_html())),
+                Node::Code(s) => out.push_str(&format!("<code>{}</code>", escape_html(s))),
+                Node::Pre { code, language: Some(lang) } => {
+                    out.push_str(&format!("<pre><code class=\"language-{}\">{}</code></pre>", escape_html(lang), escape_html(code)))
+                }
+                Node::Pre { code, language: None } => out.push_str(&format!("<pre>{}</pre>", escape_html(code))),
+                Node::Link { url, text } => out.push_str(&format!("<a href=\"{}\">{}</a>", escape_html(url), text.to_html())),
+                Node::Blockquote(t) => out.push_str(&format!("<blockquote>{}</blockquote>", t.to_html())),
+            }
+        }
+        out
+    }
+
+    pub fn to_markdown_v2(&self) -> String {
+        let mut out = String::new();
+        for n in &self.nodes {
+            match n {
+                Node::Plain(s) => out.push_str(&escape_markdown_v2(s)),
+                Node::Bold(t) => out.push_str(&format!("*{}*", t.to_markdown_v2())),
+                // `__` is read greedily as underline, so where italic and underline
+                // markers meet in the wrong order they are split with `\r`, which
+                // Telegram ignores.
+                Node::Italic(t) => {
+                    let inner = t.to_markdown_v2();
+                    let sep = if inner.starts_with("__") { "\r" } else { "" };
+                    out.push_str(&format!("_{}{}_", sep, inner))
+                }
+                Node::Underline(t) => {
+                    let inner = t.to_markdown_v2();
+                    let sep = if inner.ends_with('_') { "\r" } else { "" };
+                    out.push_str(&format!("__{}{}__", inner, sep))
+                }
+                Node::Strike(t) => out.push_str(&format!("~{}~", t.to_markdown_v2())),
+                Node::Spoiler(t) => out.push_str(&format!("||{}||", t.to_markdown_v2())),
+                Node::Code(s) => out.push_str(&format!("`{}`", escape_markdown_v2_code(s))),
+                Node::Pre { code, language } => out.push_str(&format!(
+                    "```{}\n{}\n```",
+                    language.as_deref().unwrap_or(""),
+                    escape_markdown_v2_code(code)
+                )),
+                Node::Link { url, text } => {
+                    out.push_str(&format!("[{}]({})", text.to_markdown_v2(), escape_markdown_v2_url(url)))
+                }
+                Node::Blockquote(t) => {
+                    let body = t.to_markdown_v2();
+                    let quoted: Vec<String> = body.split('\n').map(|l| format!(">{}", l)).collect();
+                    if !out.is_empty() && !out.ends_with('\n') {
+                        out.push('\n');
+                    }
+                    out.push_str(&quoted.join("\n"));
+                    out.push('\n');
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(escape_html(r#"<b>"Tom" & Jerry</b>"#), "&lt;b&gt;&quot;Tom&quot; &amp; Jerry&lt;/b&gt;");
+        assert_eq!(escape_html("plain 😀"), "plain 😀");
+    }
+
+    #[test]
+    fn escapes_markdown_v2() {
+        assert_eq!(escape_markdown_v2("1+1=2. Done! (see [a]_b*c~d`e>f#g-h|i{j}k\\)"), r"1\+1\=2\. Done\! \(see \[a\]\_b\*c\~d\`e\>f\#g\-h\|i\{j\}k\\\)");
+        assert_eq!(escape_markdown_v2_code(r"let s = `a\b`; *x*"), r"let s = \`a\\b\`; *x*");
+        assert_eq!(escape_markdown_v2_url(r"https://example.com/a_(b)\c"), r"https://example.com/a_(b\)\\c");
+    }
+
+    #[test]
+    fn refuses_legacy_markdown() {
+        assert_eq!(escape("a_b", ParseMode::Markdown), Err(LegacyMarkdown));
+        assert_eq!(Text::from("a_b").render(ParseMode::Markdown), Err(LegacyMarkdown));
+        assert_eq!(escape("a_b", ParseMode::MarkdownV2).unwrap(), r"a\_b");
+        assert_eq!(escape("a<b", ParseMode::Html).unwrap(), "a&lt;b");
+    }
+
+    fn sample() -> Text {
+        Text::new()
+            .bold("Bold & <sure>")
+            .text(" 1.5 ")
+            .italic(Text::new().text("it ").underline("u_l"))
+            .newline()
+            .strike("s")
+            .spoiler("x")
+            .code("a`b<c>")
+            .pre("fn main() {}", Some("rust"))
+            .link("https://example.com/?q=(1)&r=2", "link [1]")
+            .mention(42, "Ann")
+            .blockquote("quoted\nlines.")
+            .text("after")
+    }
+
+    #[test]
+    fn separates_italic_and_underline_markers() {
+        let t = Text::new().underline(Text::new().italic("both")).italic(Text::new().underline("both"));
+        assert_eq!(t.to_markdown_v2(), "___both_\r___\r__both___");
+    }
+
+    #[test]
+    fn renders_html() {
+        assert_eq!(
+            sample().render(ParseMode::Html).unwrap(),
+            "<b>Bold &amp; &lt;sure&gt;</b> 1.5 <i>it <u>u_l</u></i>\n<s>s</s><tg-spoiler>x</tg-spoiler><code>a`b&lt;c&gt;</code>\
+             <pre><code class=\"language-rust\">fn main() {}</code></pre>\
+             <a href=\"https://example.com/?q=(1)&amp;r=2\">link [1]</a><a href=\"tg://user?id=42\">Ann</a>\
+             <blockquote>quoted\nlines.</blockquote>after"
+        );
+    }
+
+    #[test]
+    fn renders_markdown_v2() {
+        assert_eq!(
+            sample().render(ParseMode::MarkdownV2).unwrap(),
+            "*Bold & <sure\\>* 1\\.5 _it __u\\_l___\n~s~||x||`a\\`b<c>````rust\nfn main() {}\n```\
+             [link \\[1\\]](https://example.com/?q=(1\\)&r=2)[Ann](tg://user?id=42)\n>quoted\n>lines\\.\nafter"
+        );
+    }
+}
@@ -0,0 +1,246 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use chrono::Utc;
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+use tokio::time::{Duration, Instant};
+use tracing::info;
+use crate::client::Client;
+
+// A getMe result is trusted for this long before readiness asks again.
+const TOKEN_CHECK_TTL: Duration = Duration::from_secs(60);
+// Probes have short timeouts of their own; a slow getMe fails readiness instead of hanging it.
+const TOKEN_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// State behind the liveness and readiness probes, updated by the main loop.
+pub struct Health {
+    client: Client,
+    data_dir: PathBuf,
+    started: Instant,
+    max_poll_age: Duration,
+    last_poll: Mutex<Option<Instant>>,
+    // False in webhook mode, where there is no polling loop to watch.
+    polling: Mutex<bool>,
+    last_error: Mutex<Option<(i64, String)>>,
+    token_check: Mutex<Option<(Instant, Result<String, String>)>>,
+    in_flight: Arc<AtomicUsize>,
+    handler_sem: Option<(Arc<Semaphore>, usize)>,
+}
+
+impl Health {
+    /// `max_poll_age` is how long the loop may go without a successful getUpdates
+    /// round-trip before liveness fails; it has to exceed the long-poll timeout.
+    pub fn new(client: Client, data_dir: impl Into<PathBuf>, max_poll_age: Duration, in_flight: Arc<AtomicUsize>) -> Self {
+        Self {
+            client,
+            data_dir: data_dir.into(),
+            started: Instant::now(),
+            max_poll_age,
+            last_poll: Mutex::new(None),
+            polling: Mutex::new(true),
+            last_error: Mutex::new(None),
+            token_check: Mutex::new(None),
+            in_flight,
+            handler_sem: None,
+        }
+    }
+
+    pub fn with_handler_semaphore(mut self, sem: Arc<Semaphore>, max: usize) -> Self {
+        self.handler_sem = Some((sem, max));
+        self
+    }
+
+    /// Updates arrive through the webhook, so liveness does not watch getUpdates.
+    pub fn set_webhook_mode(&self) {
+        *self.polling.lock().unwrap() = false;
+    }
+
+    pub fn poll_succeeded(&self) {
+        *self.last_poll.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub fn record_error(&self, error: &str) {
+        *self.last_error.lock().unwrap() = Some((Utc::now().timestamp(), error.to_string()));
+    }
+
+    fn last_poll_age(&self) -> Option<Duration> {
+        self.last_poll.lock().unwrap().map(|t| t.elapsed())
+    }
+
+    /// Alive while getUpdates keeps completing. Before the first poll the process
+    /// gets the same grace period since startup.
+    fn alive(&self) -> bool {
+        if !*self.polling.lock().unwrap() {
+            return true;
+        }
+        self.last_poll_age().unwrap_or_else(|| self.started.elapsed()) <= self.max_poll_age
+    }
+
+    /// The cache is not held across the getMe call, so concurrent probes never
+    /// queue behind a slow one; at worst each of them asks Telegram.
+    async fn token_status(&self) -> Result<String, String> {
+        if let Some((at, result)) = self.token_check.lock().unwrap().as_ref() {
+            if at.elapsed() < TOKEN_CHECK_TTL {
+                return result.clone();
+            }
+        }
+        let result = match tokio::time::timeout(TOKEN_CHECK_TIMEOUT, self.client.get_me()).await {
+            Ok(Ok(me)) => Ok(me.username.unwrap_or_default()),
+            Ok(Err(e)) => Err(e.to_string()),
+            Err(_) => Err(format!("getMe did not answer within {}s", TOKEN_CHECK_TIMEOUT.as_secs())),
+        };
+        *self.token_check.lock().unwrap() = Some((Instant::now(), result.clone()));
+        result
+    }
+
+    async fn data_dir_writable(&self) -> Result<(), String> {
+        let probe = self.data_dir.join(".health-probe");
+        tokio::fs::create_dir_all(&self.data_dir).await.map_err(|e| e.to_string())?;
+        tokio::fs::write(&probe, b"ok").await.map_err(|e| e.to_string())?;
+        tokio::fs::remove_file(&probe).await.map_err(|e| e.to_string())
+    }
+
+    fn summary(&self) -> Value {
+        let in_flight = self.in_flight.load(Ordering::Relaxed);
+        let running = self.handler_sem.as_ref().map(|(sem, max)| max.saturating_sub(sem.available_permits()));
+        json!({
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "mode": if *self.polling.lock().unwrap() { "polling" } else { "webhook" },
+            "last_poll_secs_ago": self.last_poll_age().map(|d| d.as_secs()),
+            "handlers_in_flight": in_flight,
+            "handlers_running": running,
+            "queue_depth": running.map(|r| in_flight.saturating_sub(r)),
+            "last_error": self.last_error.lock().unwrap().as_ref().map(|(at, msg)| json!({ "at": at, "message": msg })),
+        })
+    }
+
+    fn liveness(&self) -> (StatusCode, Value) {
+        let alive = self.alive();
+        let mut body = self.summary();
+        body["alive"] = json!(alive);
+        (if alive { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE }, body)
+    }
+
+    async fn readiness(&self) -> (StatusCode, Value) {
+        let token = self.token_status().await;
+        let data_dir = self.data_dir_writable().await;
+        let ready = token.is_ok() && data_dir.is_ok();
+        let mut body = self.summary();
+        body["ready"] = json!(ready);
+        body["token"] = match &token {
+            Ok(username) => json!({ "ok": true, "username": username }),
+            Err(e) => json!({ "ok": false, "error": e }),
+        };
+        body["data_dir"] = match &data_dir {
+            Ok(()) => json!({ "ok": true, "path": self.data_dir }),
+            Err(e) => json!({ "ok": false, "path": self.data_dir, "error": e }),
+        };
+        (if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE }, body)
+    }
+}
+
+async fn handle(health: Arc<Health>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let (status, body) = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") | (&Method::GET, "/livez") => health.liveness(),
+        (&Method::GET, "/readyz") => health.readiness().await,
+        _ => (StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+    };
+    let mut resp = Response::new(Body::from(body.to_string()));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    Ok(resp)
+}
+
+/// Serves `GET /healthz` (liveness) and `GET /readyz` (readiness) on `listen` in the background.
+pub fn serve(listen: SocketAddr, health: Arc<Health>) -> Result<(), hyper::Error> {
+    let incoming = AddrIncoming::bind(&listen)?;
+    info!("health listener on {}", incoming.local_addr());
+    let make_svc = make_service_fn(move |_conn| {
+        let health = health.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(health.clone(), req))) }
+    });
+    let server = Server::builder(incoming).serve(make_svc);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            tracing::error!("health server error: {}", e);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockBotApi, Reply, TempDir};
+
+    #[tokio::test]
+    async fn readiness_checks_token_and_data_dir() {
+        let api = MockBotApi::start().await;
+        let dir = TempDir::new("health-ready");
+        let health = Health::new(api.client(), dir.path(), Duration::from_secs(90), Arc::default());
+        let (status, body) = health.readiness().await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["token"]["username"], "mock_bot");
+        assert_eq!(body["data_dir"]["ok"], true);
+    }
+
+    #[tokio::test]
+    async fn readiness_fails_on_rejected_token() {
+        let api = MockBotApi::start().await;
+        api.enqueue("getMe", Reply::error(401, "Unauthorized"));
+        let dir = TempDir::new("health-token");
+        let health = Health::new(api.client(), dir.path(), Duration::from_secs(90), Arc::default());
+        let (status, body) = health.readiness().await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["token"]["ok"], false);
+    }
+
+    #[tokio::test]
+    async fn readiness_does_not_wait_on_a_hanging_get_me() {
+        // Accepts connections and never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                open.push(conn);
+            }
+        });
+        let dir = TempDir::new("health-hang");
+        let health = Health::new(Client::builder("1:hang").api_url(url).build(), dir.path(), Duration::from_secs(90), Arc::default());
+
+        let started = Instant::now();
+        let (a, b) = tokio::join!(health.readiness(), health.readiness());
+        assert!(started.elapsed() < TOKEN_CHECK_TIMEOUT + Duration::from_secs(1), "{:?}", started.elapsed());
+        for (status, body) in [a, b] {
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert!(body["token"]["error"].as_str().unwrap().contains("did not answer"), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn liveness_tracks_last_poll() {
+        let api = MockBotApi::start().await;
+        let dir = TempDir::new("health-live");
+        let health = Health::new(api.client(), dir.path(), Duration::from_millis(50), Arc::default());
+        health.poll_succeeded();
+        assert_eq!(health.liveness().0, StatusCode::OK);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        health.record_error("poll error: timed out");
+        let (status, body) = health.liveness();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["last_error"]["message"], "poll error: timed out");
+
+        health.set_webhook_mode();
+        assert_eq!(health.liveness().0, StatusCode::OK);
+    }
+}
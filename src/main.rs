@@ -1,8 +1,19 @@
 mod client;
 mod types;
 mod dispatch;
+mod format;
 mod commands;
 mod runtime;
+mod utils;
+mod webhook;
+mod cassette;
+mod metrics;
+mod health;
+mod payments;
+mod polls;
+#[cfg(test)]
+#[allow(dead_code)]
+mod testing;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::sync::Semaphore;
+use tracing::info;
+
+const HANDLER_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// How a handler run ended, for the handler metrics.
+#[derive(Clone, Copy, Debug)]
+pub enum Outcome {
+    Ok,
+    Error,
+    Panic,
+}
+
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    // name -> (help, rendered labels -> value)
+    counters: BTreeMap<&'static str, (&'static str, BTreeMap<String, f64>)>,
+    gauges: BTreeMap<&'static str, (&'static str, BTreeMap<String, f64>)>,
+    histograms: BTreeMap<&'static str, (&'static str, BTreeMap<String, Histogram>)>,
+}
+
+/// Process-wide metrics in the Prometheus text format. Unlike the `/stats`
+/// counters they cover the client and dispatcher as well as commands.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+    handler_sem: Mutex<Option<(Arc<Semaphore>, usize)>>,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn get() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::default)
+}
+
+fn escape_label(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v))).collect::<Vec<_>>().join(",")
+}
+
+fn series(name: &str, labels: &str) -> String {
+    if labels.is_empty() { name.to_string() } else { format!("{}{{{}}}", name, labels) }
+}
+
+impl Metrics {
+    fn inc(&self, name: &'static str, help: &'static str, labels: String, by: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.counters.entry(name).or_insert_with(|| (help, BTreeMap::new())).1.entry(labels).or_insert(0.0) += by;
+    }
+
+    fn set(&self, name: &'static str, help: &'static str, labels: String, value: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.gauges.entry(name).or_insert_with(|| (help, BTreeMap::new())).1.insert(labels, value);
+    }
+
+    fn observe(&self, name: &'static str, help: &'static str, labels: String, value: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        let h = inner
+            .histograms
+            .entry(name)
+            .or_insert_with(|| (help, BTreeMap::new()))
+            .1
+            .entry(labels)
+            .or_insert_with(|| Histogram { buckets: vec![0; HANDLER_BUCKETS.len()], sum: 0.0, count: 0 });
+        for (i, bound) in HANDLER_BUCKETS.iter().enumerate() {
+            if value <= *bound {
+                h.buckets[i] += 1;
+            }
+        }
+        h.sum += value;
+        h.count += 1;
+    }
+
+    /// `kind` is the update field that was set: message, callback_query, ...
+    pub fn update_received(&self, kind: &str) {
+        self.inc("bot_updates_total", "Updates received, by type.", labels(&[("type", kind)]), 1.0);
+    }
+
+    /// Age of the most recent message when the bot picked it up.
+    pub fn update_lag(&self, lag: Duration) {
+        self.set("bot_update_lag_seconds", "Delay between a message being sent and the bot receiving it.", String::new(), lag.as_secs_f64());
+    }
+
+    pub fn command_invoked(&self, command: &str) {
+        self.inc("bot_commands_total", "Command invocations, by command.", labels(&[("command", command)]), 1.0);
+    }
+
+    /// `handler` is the command name, or `callback` for callback query handlers.
+    pub fn handler_finished(&self, handler: &str, elapsed: Duration, outcome: Outcome) {
+        let l = labels(&[("handler", handler)]);
+        self.observe("bot_handler_duration_seconds", "Handler run time.", l.clone(), elapsed.as_secs_f64());
+        match outcome {
+            Outcome::Ok => {}
+            Outcome::Error => self.inc("bot_handler_errors_total", "Handlers that returned an error.", l, 1.0),
+            Outcome::Panic => self.inc("bot_handler_panics_total", "Handlers that panicked.", l, 1.0),
+        }
+    }
+
+    /// One HTTP exchange with the Bot API; `status` is `None` when no response arrived.
+    pub fn api_response(&self, method: &str, status: Option<u16>) {
+        let status = status.map(|s| s.to_string()).unwrap_or_else(|| "error".to_string());
+        self.inc("bot_api_requests_total", "Bot API requests, by method and HTTP status.", labels(&[("method", method), ("status", &status)]), 1.0);
+    }
+
+    /// `reason` is `too_many_requests` for 429 replies or `server_error` for 5xx.
+    pub fn api_retry(&self, method: &str, reason: &str) {
+        self.inc("bot_api_retries_total", "Bot API requests retried, by method and reason.", labels(&[("method", method), ("reason", reason)]), 1.0);
+    }
+
+    /// Time spent waiting on the global (`global`) or per-chat (`chat`) rate limiter.
+    pub fn rate_limit_wait(&self, limiter: &str, waited: Duration) {
+        self.inc("bot_rate_limiter_wait_seconds_total", "Time spent waiting for rate limiter permits.", labels(&[("limiter", limiter)]), waited.as_secs_f64());
+    }
+
+    /// Exposes permits in use of the handler semaphore, which has `max` permits.
+    pub fn set_handler_semaphore(&self, sem: Arc<Semaphore>, max: usize) {
+        *self.handler_sem.lock().unwrap() = Some((sem, max));
+    }
+
+    pub fn render(&self) -> String {
+        if let Some((sem, max)) = self.handler_sem.lock().unwrap().as_ref() {
+            let in_use = max.saturating_sub(sem.available_permits());
+            self.set("bot_handler_permits_in_use", "Handler semaphore permits currently held.", String::new(), in_use as f64);
+            self.set("bot_handler_permits_max", "Handler semaphore size.", String::new(), *max as f64);
+        }
+
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+        for (kind, families) in [("counter", &inner.counters), ("gauge", &inner.gauges)] {
+            for (name, (help, values)) in families {
+                let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+                for (l, v) in values {
+                    let _ = writeln!(out, "{} {}", series(name, l), v);
+                }
+            }
+        }
+        for (name, (help, values)) in &inner.histograms {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+            for (l, h) in values {
+                let sep = if l.is_empty() { "" } else { "," };
+                for (bound, count) in HANDLER_BUCKETS.iter().zip(&h.buckets) {
+                    let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, l, sep, bound, count);
+                }
+                let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, l, sep, h.count);
+                let _ = writeln!(out, "{} {}", series(&format!("{}_sum", name), l), h.sum);
+                let _ = writeln!(out, "{} {}", series(&format!("{}_count", name), l), h.count);
+            }
+        }
+        out
+    }
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let mut resp = Response::new(Body::empty());
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            *resp.body_mut() = Body::from(get().render());
+            resp.headers_mut().insert(CONTENT_TYPE, "text/plain; version=0.0.4".parse().unwrap());
+        }
+        _ => *resp.status_mut() = StatusCode::NOT_FOUND,
+    }
+    Ok(resp)
+}
+
+/// Serves `GET /metrics` on `listen` in the background.
+pub fn serve(listen: SocketAddr) -> Result<(), hyper::Error> {
+    let incoming = AddrIncoming::bind(&listen)?;
+    info!("metrics listener on {}/metrics", incoming.local_addr());
+    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+    let server = Server::builder(incoming).serve(make_svc);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            tracing::error!("metrics server error: {}", e);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_text_exposition() {
+        let m = Metrics::default();
+        m.api_response("sendMessage", Some(200));
+        m.api_response("sendMessage", Some(200));
+        m.api_response("getUpdates", None);
+        m.command_invoked("say \"hi\"");
+        m.handler_finished("ping", Duration::from_millis(30), Outcome::Error);
+        m.set_handler_semaphore(Arc::new(Semaphore::new(3)), 5);
+
+        let out = m.render();
+        assert!(out.contains("# TYPE bot_api_requests_total counter\n"));
+        assert!(out.contains("bot_api_requests_total{method=\"sendMessage\",status=\"200\"} 2\n"));
+        assert!(out.contains("bot_api_requests_total{method=\"getUpdates\",status=\"error\"} 1\n"));
+        assert!(out.contains("bot_commands_total{command=\"say \\\"hi\\\"\"} 1\n"));
+        assert!(out.contains("bot_handler_duration_seconds_bucket{handler=\"ping\",le=\"0.025\"} 0\n"));
+        assert!(out.contains("bot_handler_duration_seconds_bucket{handler=\"ping\",le=\"0.05\"} 1\n"));
+        assert!(out.contains("bot_handler_duration_seconds_count{handler=\"ping\"} 1\n"));
+        assert!(out.contains("bot_handler_errors_total{handler=\"ping\"} 1\n"));
+        assert!(out.contains("bot_handler_permits_in_use 2\n"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use crate::types::SuccessfulPayment;
+use crate::utils::{load_json, save_json};
+
+/// A payment as kept in the ledger.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Payment {
+    /// `telegram_payment_charge_id`, which also identifies the payment for refunds.
+    pub charge_id: String,
+    pub provider_charge_id: String,
+    pub user_id: i64,
+    pub currency: String,
+    pub total_amount: i64,
+    pub payload: String,
+    pub paid_at: i64,
+    pub refunded_at: Option<i64>,
+}
+
+/// Every payment the bot has received, by charge id. Unlike the KV store it is
+/// written to disk on each change rather than by the autosave loop, so a crash
+/// cannot lose a payment that was already acknowledged.
+#[derive(Clone)]
+pub struct Ledger {
+    path: Arc<PathBuf>,
+    payments: Arc<RwLock<BTreeMap<String, Payment>>>,
+}
+
+impl Ledger {
+    /// Loads the ledger at `path`, or starts an empty one if the file does not
+    /// exist. A file that does not parse is an error rather than an empty
+    /// ledger, which would overwrite it on the next payment.
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let payments = load_json(&path).await?;
+        Ok(Self { path: Arc::new(path), payments: Arc::new(RwLock::new(payments)) })
+    }
+
+    // Replaces the file in one rename, so a crash mid-write keeps the old ledger.
+    async fn save(&self, payments: &BTreeMap<String, Payment>) -> io::Result<()> {
+        save_json(&self.path, payments).await
+    }
+
+    /// Records a successful payment by `user_id`. Returns `false` if it was
+    /// already recorded, as happens when an update is delivered twice.
+    pub async fn record(&self, user_id: i64, sp: &SuccessfulPayment) -> io::Result<bool> {
+        let mut payments = self.payments.write().await;
+        if payments.contains_key(&sp.telegram_payment_charge_id) {
+            return Ok(false);
+        }
+        let payment = Payment {
+            charge_id: sp.telegram_payment_charge_id.clone(),
+            provider_charge_id: sp.provider_payment_charge_id.clone(),
+            user_id,
+            currency: sp.currency.clone(),
+            total_amount: sp.total_amount,
+            payload: sp.invoice_payload.clone(),
+            paid_at: Utc::now().timestamp(),
+            refunded_at: None,
+        };
+        payments.insert(payment.charge_id.clone(), payment);
+        self.save(&payments).await?;
+        Ok(true)
+    }
+
+    /// Marks a payment refunded and returns it; `None` if the charge is unknown.
+    pub async fn mark_refunded(&self, charge_id: &str) -> io::Result<Option<Payment>> {
+        let mut payments = self.payments.write().await;
+        let Some(payment) = payments.get_mut(charge_id) else { return Ok(None) };
+        if payment.refunded_at.is_none() {
+            payment.refunded_at = Some(Utc::now().timestamp());
+        }
+        let payment = payment.clone();
+        self.save(&payments).await?;
+        Ok(Some(payment))
+    }
+
+    pub async fn get(&self, charge_id: &str) -> Option<Payment> {
+        self.payments.read().await.get(charge_id).cloned()
+    }
+
+    /// The earliest payment by `user_id` for `payload` that was not refunded.
+    pub async fn active(&self, user_id: i64, payload: &str) -> Option<Payment> {
+        self.payments
+            .read()
+            .await
+            .values()
+            .filter(|p| p.user_id == user_id && p.payload == payload && p.refunded_at.is_none())
+            .min_by_key(|p| p.paid_at)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use serde_json::json;
+
+    fn payment(charge_id: &str) -> SuccessfulPayment {
+        serde_json::from_value(json!({
+            "currency": "XTR",
+            "total_amount": 50,
+            "invoice_payload": "premium",
+            "telegram_payment_charge_id": charge_id,
+            "provider_payment_charge_id": "",
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn persists_payments_and_refunds() {
+        let dir = TempDir::new("ledger");
+        let path = dir.path().join("payments.json");
+
+        let ledger = Ledger::open(&path).await.unwrap();
+        assert!(ledger.record(42, &payment("c1")).await.unwrap());
+        assert!(!ledger.record(42, &payment("c1")).await.unwrap());
+        assert_eq!(ledger.active(42, "premium").await.map(|p| p.charge_id), Some("c1".to_string()));
+
+        let reopened = Ledger::open(&path).await.unwrap();
+        assert_eq!(reopened.get("c1").await.map(|p| p.total_amount), Some(50));
+        assert!(reopened.mark_refunded("c1").await.unwrap().unwrap().refunded_at.is_some());
+        assert!(reopened.mark_refunded("missing").await.unwrap().is_none());
+        assert!(Ledger::open(&path).await.unwrap().active(42, "premium").await.is_none());
+
+        tokio::fs::write(&path, b"not json").await.unwrap();
+        assert_eq!(Ledger::open(&path).await.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use crate::utils::{load_json, save_json};
+use crate::types::{Poll, PollAnswer};
+
+/// A poll the bot sent, with the latest results Telegram reported for it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PollRecord {
+    pub poll_id: String,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub question: String,
+    pub options: Vec<String>,
+    pub quiz: bool,
+    pub correct_option_id: Option<u32>,
+    pub anonymous: bool,
+    pub created_by: i64,
+    pub created_at: i64,
+    /// When the bot stops the poll itself, for polls open longer than Telegram's
+    /// own `close_date` allows.
+    pub closes_at: Option<i64>,
+    pub closed: bool,
+    /// Votes per option, from `poll` updates.
+    pub counts: Vec<u32>,
+    pub total_voters: u32,
+    /// The options each voter picked, by user id, from `poll_answer` updates.
+    /// Stays empty for anonymous polls.
+    pub votes: BTreeMap<i64, Vote>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Vote {
+    pub name: String,
+    pub options: Vec<u32>,
+}
+
+impl PollRecord {
+    /// The question with the votes and share of each option, plus who voted for
+    /// what in non-anonymous polls.
+    pub fn summary(&self) -> String {
+        let mut out = format!("{}{}\n", self.question, if self.closed { " (closed)" } else { "" });
+        for (i, option) in self.options.iter().enumerate() {
+            let count = self.counts.get(i).copied().unwrap_or(0);
+            let share = (count * 100).checked_div(self.total_voters).unwrap_or(0);
+            let mark = if self.correct_option_id == Some(i as u32) { " ✓" } else { "" };
+            let _ = write!(out, "\n{}{}: {} ({}%)", option, mark, count, share);
+            let voters: Vec<&str> = self.votes.values().filter(|v| v.options.contains(&(i as u32))).map(|v| v.name.as_str()).collect();
+            if !voters.is_empty() {
+                let _ = write!(out, " — {}", voters.join(", "));
+            }
+        }
+        let _ = write!(out, "\n\nVoters: {}", self.total_voters);
+        out
+    }
+}
+
+/// Polls by id, written to disk on every change.
+#[derive(Clone)]
+pub struct PollStore {
+    path: Arc<PathBuf>,
+    polls: Arc<RwLock<BTreeMap<String, PollRecord>>>,
+}
+
+impl PollStore {
+    /// Loads the store at `path`, or starts an empty one if the file does not exist.
+    pub async fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let polls = load_json(&path).await?;
+        Ok(Self { path: Arc::new(path), polls: Arc::new(RwLock::new(polls)) })
+    }
+
+    async fn save(&self, polls: &BTreeMap<String, PollRecord>) -> io::Result<()> {
+        save_json(&self.path, polls).await
+    }
+
+    pub async fn insert(&self, record: PollRecord) -> io::Result<()> {
+        let mut polls = self.polls.write().await;
+        polls.insert(record.poll_id.clone(), record);
+        self.save(&polls).await
+    }
+
+    /// Takes the counts from a `poll` update, or the result of `stopPoll`.
+    /// Returns the updated record; `None` for polls the bot did not send.
+    pub async fn apply_poll(&self, poll: &Poll) -> io::Result<Option<PollRecord>> {
+        let mut polls = self.polls.write().await;
+        let Some(record) = polls.get_mut(&poll.id) else { return Ok(None) };
+        record.counts = poll.options.iter().map(|o| o.voter_count).collect();
+        record.total_voters = poll.total_voter_count;
+        record.closed |= poll.is_closed;
+        let record = record.clone();
+        self.save(&polls).await?;
+        Ok(Some(record))
+    }
+
+    /// Records a vote, or its retraction when `option_ids` is empty.
+    pub async fn apply_answer(&self, answer: &PollAnswer) -> io::Result<bool> {
+        let Some(user) = &answer.user else { return Ok(false) };
+        let mut polls = self.polls.write().await;
+        let Some(record) = polls.get_mut(&answer.poll_id) else { return Ok(false) };
+        if answer.option_ids.is_empty() {
+            record.votes.remove(&user.id);
+        } else {
+            let name = user.username.as_ref().map(|u| format!("@{}", u)).unwrap_or_else(|| user.first_name.clone());
+            record.votes.insert(user.id, Vote { name, options: answer.option_ids.clone() });
+        }
+        self.save(&polls).await?;
+        Ok(true)
+    }
+
+    /// The most recently created poll in `chat_id`.
+    pub async fn latest_in_chat(&self, chat_id: i64) -> Option<PollRecord> {
+        self.polls.read().await.values().filter(|p| p.chat_id == chat_id).max_by_key(|p| (p.created_at, p.message_id)).cloned()
+    }
+
+    pub async fn by_message(&self, chat_id: i64, message_id: i64) -> Option<PollRecord> {
+        self.polls.read().await.values().find(|p| p.chat_id == chat_id && p.message_id == message_id).cloned()
+    }
+
+    /// Open polls whose `closes_at` has passed.
+    pub async fn due(&self, now: i64) -> Vec<PollRecord> {
+        self.polls.read().await.values().filter(|p| !p.closed && p.closes_at.is_some_and(|t| t <= now)).cloned().collect()
+    }
+
+    /// Moves the polls of a group to the supergroup it was upgraded to.
+    pub async fn migrate_chat(&self, from: i64, to: i64) -> io::Result<()> {
+        let mut polls = self.polls.write().await;
+        let mut moved = false;
+        for record in polls.values_mut().filter(|p| p.chat_id == from) {
+            record.chat_id = to;
+            moved = true;
+        }
+        if moved {
+            self.save(&polls).await?;
+        }
+        Ok(())
+    }
+
+    /// Marks a poll closed, for when Telegram reports it can no longer be stopped.
+    pub async fn mark_closed(&self, poll_id: &str) -> io::Result<()> {
+        let mut polls = self.polls.write().await;
+        if let Some(record) = polls.get_mut(poll_id) {
+            record.closed = true;
+        }
+        self.save(&polls).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use serde_json::json;
+
+    fn record() -> PollRecord {
+        PollRecord {
+            poll_id: "p1".into(),
+            chat_id: -100,
+            message_id: 5,
+            question: "Lunch?".into(),
+            options: vec!["Pizza".into(), "Sushi".into()],
+            quiz: false,
+            correct_option_id: None,
+            anonymous: false,
+            created_by: 1,
+            created_at: 0,
+            closes_at: Some(10),
+            closed: false,
+            counts: vec![0, 0],
+            total_voters: 0,
+            votes: BTreeMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn tracks_results_across_restarts() {
+        let dir = TempDir::new("polls");
+        let path = dir.path().join("polls.json");
+        let store = PollStore::open(&path).await.unwrap();
+        store.insert(record()).await.unwrap();
+
+        let answer: PollAnswer = serde_json::from_value(json!({ "poll_id": "p1", "user": { "id": 7, "is_bot": false, "first_name": "Ann" }, "option_ids": [1] })).unwrap();
+        assert!(store.apply_answer(&answer).await.unwrap());
+        let poll: Poll = serde_json::from_value(json!({
+            "id": "p1", "question": "Lunch?", "total_voter_count": 1, "is_closed": false, "is_anonymous": false,
+            "type": "regular", "allows_multiple_answers": false,
+            "options": [{ "text": "Pizza", "voter_count": 0 }, { "text": "Sushi", "voter_count": 1 }],
+        }))
+        .unwrap();
+        store.apply_poll(&poll).await.unwrap();
+        assert_eq!(store.due(9).await.len(), 0);
+        assert_eq!(store.due(10).await.len(), 1);
+
+        let reopened = PollStore::open(&path).await.unwrap();
+        let summary = reopened.latest_in_chat(-100).await.unwrap().summary();
+        assert!(summary.contains("Sushi: 1 (100%) — Ann"), "{}", summary);
+        assert!(summary.contains("Pizza: 0 (0%)"), "{}", summary);
+
+        reopened.mark_closed("p1").await.unwrap();
+        assert!(reopened.due(10).await.is_empty());
+
+        reopened.migrate_chat(-100, -1001).await.unwrap();
+        assert!(reopened.latest_in_chat(-100).await.is_none());
+        assert_eq!(PollStore::open(&path).await.unwrap().latest_in_chat(-1001).await.map(|r| r.poll_id), Some("p1".to_string()));
+    }
+}
@@ -1,22 +1,122 @@
 use std::env;
+use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::{HashMap, HashSet};
 use tokio::time::{sleep, Duration};
-use crate::client::Client;
+use crate::client::{Client, BotError};
+use crate::cassette::ReplayReport;
 use crate::dispatch::Dispatcher;
+use crate::polls::PollStore;
+use crate::types::{Update, UpdateKind};
+use crate::utils::parse_command;
+use crate::webhook::{WebhookConfig, WebhookServer};
+use crate::health::Health;
+use tokio::sync::mpsc;
 use tokio::fs as tokio_fs;
 use chrono::Utc;
 
 type KvStore = Arc<RwLock<HashMap<String, String>>>;
+type KvOwners = Arc<RwLock<HashMap<String, i64>>>;
 type Users = Arc<RwLock<HashSet<i64>>>;
 type Counters = Arc<RwLock<HashMap<String, u64>>>;
+type Cooldowns = Arc<RwLock<HashMap<i64, u64>>>;
+type Bursts = Arc<RwLock<HashMap<i64, Vec<u64>>>>;
 
 const DATA_DIR: &str = "data";
 const KV_FILE: &str = "data/kv.json";
+const KV_OWNERS_FILE: &str = "data/kv_owners.json";
 const USERS_FILE: &str = "data/users.json";
+const PAYMENTS_FILE: &str = "payments.json";
+const PREMIUM_PRICE_STARS: i64 = 50;
+const POLLS_FILE: &str = "polls.json";
+const POLL_SWEEP_INTERVAL_SECS: u64 = 30;
 const AUTOSAVE_INTERVAL_SECS: u64 = 30;
 const COOLDOWN_SECONDS: u64 = 2;
+const WEBHOOK_LISTEN: &str = "0.0.0.0:8443";
+const POLL_TIMEOUT_SECS: u64 = 30;
+
+enum UpdateSource {
+    Polling,
+    Webhook(mpsc::Receiver<Update>),
+}
+
+async fn next_updates(client: &Client, source: &mut UpdateSource, offset: i64, allowed: &[UpdateKind]) -> Result<Vec<Update>, BotError> {
+    match source {
+        UpdateSource::Polling => client.get_updates(offset, POLL_TIMEOUT_SECS, allowed).await,
+        UpdateSource::Webhook(rx) => match rx.recv().await {
+            Some(u) => {
+                let mut batch = vec![u];
+                while let Ok(u) = rx.try_recv() {
+                    batch.push(u);
+                }
+                Ok(batch)
+            }
+            None => Err(BotError::Api("webhook listener stopped".into())),
+        },
+    }
+}
+
+/// Parses a comma-separated list of update kinds; blank items are skipped, so an
+/// empty list means no override.
+fn parse_allowed_updates(list: &str) -> Result<Vec<UpdateKind>, String> {
+    list.split(',').map(str::trim).filter(|k| !k.is_empty()).map(str::parse).collect()
+}
+
+/// Opens the payments ledger and poll store in `dir` and registers the handlers
+/// that use them. Returns the poll store for the sweep that closes due polls.
+async fn register_stores(disp: &mut Dispatcher, admin: Option<i64>, dir: &Path, premium_price: i64) -> std::io::Result<PollStore> {
+    let ledger = crate::payments::Ledger::open(dir.join(PAYMENTS_FILE)).await?;
+    crate::commands::payments::register(disp, admin, ledger, premium_price);
+    let polls = PollStore::open(dir.join(POLLS_FILE)).await?;
+    crate::commands::polls::register(disp, admin, polls.clone());
+    Ok(polls)
+}
+
+/// Replays the cassette at `path` through `disp`. The payment and poll handlers
+/// save on every change, so they get copies of the stores in `data_dir` in a
+/// scratch directory that is removed afterwards.
+async fn replay_cassette(path: &str, mut disp: Dispatcher, admin: Option<i64>, data_dir: &Path, premium_price: i64) -> Result<ReplayReport, Box<dyn std::error::Error>> {
+    let entries = crate::cassette::load(path)?;
+    let scratch = std::env::temp_dir().join(format!("bot-replay-{}", std::process::id()));
+    tokio_fs::create_dir_all(&scratch).await?;
+    let report = async {
+        for file in [PAYMENTS_FILE, POLLS_FILE] {
+            match tokio_fs::copy(data_dir.join(file), scratch.join(file)).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        register_stores(&mut disp, admin, &scratch, premium_price).await?;
+        crate::cassette::replay(&entries, &disp).await
+    }
+    .await;
+    let _ = tokio_fs::remove_dir_all(&scratch).await;
+    Ok(report?)
+}
+
+/// Moves per-chat state from a group to the supergroup it was upgraded to.
+async fn migrate_chat(from: i64, to: i64, users: &Users, cooldowns: &Cooldowns, bursts: &Bursts, polls: &PollStore) {
+    let mut us = users.write().await;
+    if us.remove(&from) {
+        us.insert(to);
+    }
+    drop(us);
+    let mut cds = cooldowns.write().await;
+    if let Some(v) = cds.remove(&from) {
+        cds.insert(to, v);
+    }
+    drop(cds);
+    let mut bm = bursts.write().await;
+    if let Some(v) = bm.remove(&from) {
+        bm.insert(to, v);
+    }
+    drop(bm);
+    if let Err(e) = polls.migrate_chat(from, to).await {
+        tracing::warn!("failed to move polls of chat {} to {}: {}", from, to, e);
+    }
+}
 
 pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
     let _ = dotenvy::dotenv();
@@ -38,19 +138,48 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
 
     let cooldown_seconds: u64 = env::var("COOLDOWN_SECONDS").ok().and_then(|s| s.parse().ok()).unwrap_or(COOLDOWN_SECONDS);
 
-    let client = Client::builder(token.clone()).build();
+    let mut builder = Client::builder(token.clone());
+    if let Ok(url) = env::var("TELEGRAM_API_URL") {
+        builder = builder.api_url(url);
+    }
+    if let Ok(url) = env::var("TELEGRAM_FILE_URL") {
+        builder = builder.file_url(url);
+    }
+    if let Ok(url) = env::var("TELEGRAM_PROXY") {
+        builder = builder.proxy(url);
+        if let (Ok(user), Ok(pass)) = (env::var("TELEGRAM_PROXY_USER"), env::var("TELEGRAM_PROXY_PASSWORD")) {
+            builder = builder.proxy_auth(user, pass);
+        }
+    }
+    if let Some(secs) = env::var("HTTP_CONNECT_TIMEOUT_SECS").ok().and_then(|s| s.parse().ok()) {
+        builder = builder.connect_timeout(Duration::from_secs(secs));
+    }
+    if let Some(secs) = env::var("HTTP_REQUEST_TIMEOUT_SECS").ok().and_then(|s| s.parse().ok()) {
+        builder = builder.request_timeout(Duration::from_secs(secs));
+    }
+    if let Ok(path) = env::var("TELEGRAM_CA_CERT") {
+        builder = builder.root_certificate_pem(tokio_fs::read(&path).await?);
+    }
+    if let Ok(ua) = env::var("HTTP_USER_AGENT") {
+        builder = builder.user_agent(ua);
+    }
+    let mut client = builder.try_build()?;
+    if let Ok(path) = env::var("RECORD_CASSETTE") {
+        tracing::info!("recording updates and API calls to {}", path);
+        client.recorder = Some(crate::cassette::Recorder::to_file(&path).await?);
+    }
     let mut offset: i64 = 0;
 
     {
-        let token_for_hook = token.clone();
+        let base_for_hook = client.base.clone();
         let admin_for_hook = admin;
         std::panic::set_hook(Box::new(move |info| {
             let msg = format!("Bot panic: {}\n", info);
             let _ = std::fs::write("data/panic.log", msg.as_bytes());
             if let Some(aid) = admin_for_hook {
-                let token = token_for_hook.clone();
+                let base = base_for_hook.clone();
                 std::thread::spawn(move || {
-                    let url = format!("https://api.telegram.org/bot{}/sendMessage", token);
+                    let url = format!("{}/sendMessage", base);
                     let body = serde_json::json!({"chat_id": aid, "text": msg});
                     let client = reqwest::blocking::Client::new();
                     let _ = client.post(&url).json(&body).send();
@@ -61,24 +190,22 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let kv_map = if let Ok(b) = tokio_fs::read(KV_FILE).await {
-        match serde_json::from_slice::<HashMap<String, String>>(&b) {
-            Ok(m) => m,
-            Err(_) => HashMap::new(),
-        }
+        serde_json::from_slice::<HashMap<String, String>>(&b).unwrap_or_default()
+    } else { HashMap::new() };
+    let kv_owners_map = if let Ok(b) = tokio_fs::read(KV_OWNERS_FILE).await {
+        serde_json::from_slice::<HashMap<String, i64>>(&b).unwrap_or_default()
     } else { HashMap::new() };
     let users_set = if let Ok(b) = tokio_fs::read(USERS_FILE).await {
-        match serde_json::from_slice::<HashSet<i64>>(&b) {
-            Ok(s) => s,
-            Err(_) => HashSet::new(),
-        }
+        serde_json::from_slice::<HashSet<i64>>(&b).unwrap_or_default()
     } else { HashSet::new() };
 
     let kv: KvStore = Arc::new(RwLock::new(kv_map));
+    let kv_owners: KvOwners = Arc::new(RwLock::new(kv_owners_map));
     let users: Users = Arc::new(RwLock::new(users_set));
     let counters: Counters = Arc::new(RwLock::new(HashMap::new()));
 
-    let cooldowns: Arc<RwLock<HashMap<i64, u64>>> = Arc::new(RwLock::new(HashMap::new()));
-    let bursts: Arc<RwLock<HashMap<i64, Vec<u64>>>> = Arc::new(RwLock::new(HashMap::new()));
+    let cooldowns: Cooldowns = Arc::new(RwLock::new(HashMap::new()));
+    let bursts: Bursts = Arc::new(RwLock::new(HashMap::new()));
 
     let mut disp = Dispatcher::new();
 
@@ -101,13 +228,52 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
     });
     let kb_keyboard = keyboard_markup.clone();
 
-    crate::commands::register(&mut disp, admin, kv.clone(), users.clone(), counters.clone(), kb_help, kb_start, kb_keyboard);
+    crate::commands::register(&mut disp, admin, kv.clone(), kv_owners.clone(), users.clone(), counters.clone(), kb_help, kb_start, kb_keyboard);
 
+    let premium_price: i64 = env::var("PREMIUM_PRICE_STARS").ok().and_then(|s| s.parse().ok()).unwrap_or(PREMIUM_PRICE_STARS);
     disp.set_admin(admin);
 
+    // Replay feeds a recorded cassette through the handlers against a stub API
+    // instead of running the bot.
+    if let Ok(path) = env::var("REPLAY_CASSETTE") {
+        let report = replay_cassette(&path, disp, admin, Path::new(DATA_DIR), premium_price).await?;
+        println!("{}", report);
+        return if report.is_clean() { Ok(()) } else { Err("replay differs from the recording".into()) };
+    }
+
+    let polls = register_stores(&mut disp, admin, Path::new(DATA_DIR), premium_price).await?;
+
+    match client.get_me().await {
+        Ok(me) => {
+            tracing::info!("Authorized as @{} (id {})", me.username.as_deref().unwrap_or("?"), me.id);
+            client.set_me(me);
+        }
+        // 401 means the token itself is wrong; nothing else will work either.
+        Err(e) if e.error_code() == Some(401) => return Err(format!("invalid bot token: {}", e).into()),
+        Err(e) => tracing::warn!("getMe failed, /cmd@botname addressing is not checked: {}", e),
+    }
+
+    if let Err(e) = disp.registry().sync(&client, admin).await {
+        tracing::warn!("failed to sync command menu: {}", e);
+    }
+
     let max_handlers: usize = env::var("MAX_CONCURRENT_HANDLERS").ok().and_then(|s| s.parse().ok()).unwrap_or(50);
     let sem = Arc::new(tokio::sync::Semaphore::new(max_handlers));
     disp.set_concurrency_limit(sem.clone());
+    crate::metrics::get().set_handler_semaphore(sem.clone(), max_handlers);
+
+    if let Ok(listen) = env::var("METRICS_LISTEN") {
+        crate::metrics::serve(listen.parse()?)?;
+    }
+
+    let max_poll_age: u64 = env::var("HEALTH_MAX_POLL_AGE_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(POLL_TIMEOUT_SECS * 3);
+    let health = Arc::new(
+        Health::new(client.clone(), DATA_DIR, Duration::from_secs(max_poll_age), disp.in_flight())
+            .with_handler_semaphore(sem.clone(), max_handlers),
+    );
+    if let Ok(listen) = env::var("HEALTH_LISTEN") {
+        crate::health::serve(listen.parse()?, health.clone())?;
+    }
 
     let window_secs: u64 = env::var("BURST_WINDOW_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(30);
     let max_per_window: usize = env::var("MAX_PER_WINDOW").ok().and_then(|s| s.parse().ok()).unwrap_or(10);
@@ -116,46 +282,128 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
 
     {
         let kv_s = kv.clone();
+        let kv_owners_s = kv_owners.clone();
         let users_s = users.clone();
+        let cooldowns_s = cooldowns.clone();
+        let bursts_s = bursts.clone();
+        let polls_s = polls.clone();
+        let client_s = client.clone();
             let autosave_interval_secs: u64 = env::var("AUTOSAVE_INTERVAL_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(AUTOSAVE_INTERVAL_SECS);
             tokio::spawn(async move {
                 loop {
                     sleep(Duration::from_secs(autosave_interval_secs)).await;
+                    // Sends that hit a migrated group are rerouted by the client; fold
+                    // those migrations into our own state before persisting it.
+                    for (from, to) in client_s.chat_migrations() {
+                        migrate_chat(from, to, &users_s, &cooldowns_s, &bursts_s, &polls_s).await;
+                    }
                     let kv_json = serde_json::to_vec(&*kv_s.read().await).unwrap_or_default();
                     let _ = tokio_fs::write(KV_FILE, kv_json).await;
+                    let kv_owners_json = serde_json::to_vec(&*kv_owners_s.read().await).unwrap_or_default();
+                    let _ = tokio_fs::write(KV_OWNERS_FILE, kv_owners_json).await;
                     let users_json = serde_json::to_vec(&*users_s.read().await).unwrap_or_default();
                     let _ = tokio_fs::write(USERS_FILE, users_json).await;
                 }
             });
     }
 
-    tracing::info!("Starting polling bot with dispatcher... (press Ctrl+C to stop)");
+    {
+        // Telegram only closes polls by itself up to 10 minutes ahead; longer
+        // `/poll --close` deadlines are enforced here.
+        let client_p = client.clone();
+        let polls_p = polls.clone();
+        tokio::spawn(async move {
+            loop {
+                sleep(Duration::from_secs(POLL_SWEEP_INTERVAL_SECS)).await;
+                crate::commands::polls::close_due(&client_p, &polls_p).await;
+            }
+        });
+    }
+
+    // ALLOWED_UPDATES=message,callback_query overrides the kinds derived from the handlers.
+    let allowed_updates = match env::var("ALLOWED_UPDATES").ok().map(|list| parse_allowed_updates(&list)).transpose()? {
+        Some(kinds) if !kinds.is_empty() => {
+            if !kinds.contains(&UpdateKind::Message) {
+                tracing::warn!("ALLOWED_UPDATES does not include message, so no command will be received");
+            }
+            kinds
+        }
+        _ => disp.allowed_updates(),
+    };
+
+    let mut source = match env::var("WEBHOOK_URL").ok().filter(|s| !s.is_empty()) {
+        Some(url) => {
+            let listen = env::var("WEBHOOK_LISTEN").unwrap_or_else(|_| WEBHOOK_LISTEN.to_string()).parse()?;
+            let path = match env::var("WEBHOOK_PATH") {
+                Ok(p) => p,
+                Err(_) => reqwest::Url::parse(&url)?.path().to_string(),
+            };
+            let secret = match env::var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty()) {
+                Some(secret) => secret,
+                None => {
+                    tracing::info!("WEBHOOK_SECRET is not set, using a random secret for this run");
+                    WebhookConfig::random_secret()?
+                }
+            };
+            let server = WebhookServer::bind(WebhookConfig { listen, path, secret: secret.clone() })?;
+            let (tx, rx) = mpsc::channel(256);
+            server.spawn(tx);
+            client.set_webhook(&url, Some(&secret), false, Some(allowed_updates.clone())).await?;
+            health.set_webhook_mode();
+            tracing::info!("Starting webhook bot with dispatcher at {}... (press Ctrl+C to stop)", url);
+            UpdateSource::Webhook(rx)
+        }
+        None => {
+            if let Err(e) = client.delete_webhook(false).await {
+                tracing::warn!("deleteWebhook failed: {}", e);
+            }
+            tracing::info!("Starting polling bot with dispatcher... (press Ctrl+C to stop)");
+            UpdateSource::Polling
+        }
+    };
 
     loop {
         tokio::select! {
             _ = tokio::signal::ctrl_c() => {
-                println!("Shutdown signal received, stopping polling...");
+                println!("Shutdown signal received, stopping...");
                 if let Some(aid) = admin {
                     let _ = client.send_message(aid, "Bot is shutting down", None).await;
                 }
                 break;
             }
-            res = client.get_updates(offset, 30) => {
+            res = next_updates(&client, &mut source, offset, &allowed_updates) => {
                 match res {
                     Ok(updates) => {
+                        health.poll_succeeded();
                         for u in updates {
                             offset = u.update_id + 1;
+                            crate::metrics::get().update_received(u.kind().map(|k| k.as_str()).unwrap_or("other"));
+                            if u.kind().is_none() {
+                                tracing::debug!("update {} of a kind we do not model: {}", u.update_id, u.raw());
+                            }
+                            if let Some(date) = u.message.as_ref().and_then(|m| m.date) {
+                                let lag = (Utc::now().timestamp() - date).max(0) as u64;
+                                crate::metrics::get().update_lag(Duration::from_secs(lag));
+                            }
+                            if let Some(rec) = &client.recorder {
+                                rec.begin_update(u.update_id);
+                            }
 
                             if let Some(msg) = &u.message {
                                 let mut us = users.write().await;
                                 us.insert(msg.chat.id);
                             }
 
-                            if let Some(msg) = u.message {
+                            if let Some((from, to)) = u.message.as_ref().and_then(|m| m.chat_migration()) {
+                                tracing::info!("chat {} migrated to supergroup {}", from, to);
+                                client.record_chat_migration(from, to);
+                                migrate_chat(from, to, &users, &cooldowns, &bursts, &polls).await;
+                            }
+
+                            if let Some(msg) = &u.message {
 
-                                if let Some(text) = &msg.text {
-                                    if text.starts_with('/') {
-                                        let cmd = text.split_whitespace().next().unwrap_or("").trim_start_matches('/').to_string();
+                                if let Some(text) = msg.text_or_caption() {
+                                    if let Some(cmd) = parse_command(text, client.bot_username()) {
                                         let mut ctr = counters.write().await;
                                         *ctr.entry(cmd).or_insert(0) += 1;
 
@@ -182,7 +430,7 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
                                         cds.insert(uid, now);
                                     }
                                 }
-                                tracing::info!("Message from {}: {}", msg.chat.id, msg.text.clone().unwrap_or_default());
+                                tracing::info!("Message from {}: {}", msg.chat.id, msg.text_or_caption().unwrap_or_default());
 
                                 if let Some(aid) = admin {
                                     if let Some(contact) = &msg.contact {
@@ -197,16 +445,14 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
                                     }
                                 }
 
-                                disp.dispatch(client.clone(), msg).await;
                             }
 
-                            if let Some(cb) = u.callback_query {
-                                disp.dispatch_callback(client.clone(), cb).await;
-                            }
+                            disp.dispatch_update(client.clone(), u).await;
                         }
                     }
                     Err(e) => {
                         tracing::error!("poll error: {}", e);
+                        health.record_error(&e.to_string());
                         sleep(Duration::from_secs(2)).await;
                     }
                 }
@@ -216,3 +462,56 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cassette::Entry;
+    use crate::testing::TempDir;
+    use serde_json::json;
+
+    #[test]
+    fn parses_allowed_updates() {
+        assert_eq!(parse_allowed_updates(""), Ok(vec![]));
+        assert_eq!(parse_allowed_updates(" , "), Ok(vec![]));
+        assert_eq!(parse_allowed_updates("message, poll_answer,"), Ok(vec![UpdateKind::Message, UpdateKind::PollAnswer]));
+        assert!(parse_allowed_updates("message,nope").is_err());
+    }
+
+    #[tokio::test]
+    async fn replay_leaves_the_data_files_alone() {
+        let data = TempDir::new("replay-data");
+        std::fs::create_dir_all(data.path()).unwrap();
+        for file in [PAYMENTS_FILE, POLLS_FILE] {
+            std::fs::write(data.path().join(file), "{}").unwrap();
+        }
+        let chat = json!({ "id": 1, "type": "private" });
+        let from = json!({ "id": 1, "is_bot": false, "first_name": "Admin" });
+        let payment = json!({ "currency": "XTR", "total_amount": 50, "invoice_payload": "premium", "telegram_payment_charge_id": "c1", "provider_payment_charge_id": "" });
+        let poll = json!({
+            "id": "p1", "question": "Q", "total_voter_count": 0, "is_closed": false, "is_anonymous": false,
+            "type": "regular", "allows_multiple_answers": false,
+            "options": [{ "text": "a", "voter_count": 0 }, { "text": "b", "voter_count": 0 }],
+        });
+        let entries = [
+            Entry::Update { update: json!({ "update_id": 1, "message": { "message_id": 1, "date": 0, "chat": chat, "from": from, "successful_payment": payment } }) },
+            Entry::Update { update: json!({ "update_id": 2, "message": { "message_id": 2, "date": 0, "chat": chat, "from": from, "text": r#"/poll "Q" a b"# } }) },
+            Entry::Call {
+                method: "sendPoll".into(),
+                params: json!({}),
+                status: 200,
+                response: json!({ "ok": true, "result": { "message_id": 3, "date": 0, "chat": chat, "poll": poll } }),
+                update_id: Some(2),
+            },
+        ];
+        let cassette = data.path().join("cassette.jsonl");
+        std::fs::write(&cassette, entries.iter().map(|e| serde_json::to_string(e).unwrap() + "\n").collect::<String>()).unwrap();
+
+        let report = replay_cassette(cassette.to_str().unwrap(), Dispatcher::new(), Some(1), data.path(), 50).await.unwrap();
+        assert_eq!(report.updates, 2);
+        for file in [PAYMENTS_FILE, POLLS_FILE] {
+            assert_eq!(std::fs::read_to_string(data.path().join(file)).unwrap(), "{}", "{} changed", file);
+        }
+        assert!(!std::env::temp_dir().join(format!("bot-replay-{}", std::process::id())).exists());
+    }
+}
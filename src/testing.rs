@@ -0,0 +1,612 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Map, Value};
+use tokio::sync::{oneshot, Notify};
+use tokio::time::{timeout, Duration};
+use crate::client::Client;
+use crate::dispatch::Dispatcher;
+
+pub const MOCK_TOKEN: &str = "123456:TEST";
+pub const MOCK_BOT_ID: i64 = 123456;
+pub const MOCK_BOT_USERNAME: &str = "mock_bot";
+
+/// How long `wait_for` waits before failing the test.
+const WAIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A file part of a multipart upload.
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    pub field: String,
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// One request received by the mock, in arrival order.
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub method: String,
+    /// JSON body, or the text fields of a multipart body (all as strings).
+    pub params: Value,
+    pub files: Vec<UploadedFile>,
+}
+
+impl Call {
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.params.get(key).and_then(|v| v.as_str())
+    }
+
+    /// An integer parameter, whether it was sent as JSON number or multipart text.
+    pub fn i64(&self, key: &str) -> Option<i64> {
+        match self.params.get(key)? {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// A scripted reply, returned instead of the default result for one call.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub body: String,
+    pub retry_after_header: Option<u64>,
+}
+
+impl Reply {
+    pub fn ok(result: Value) -> Self {
+        Self::json(200, json!({ "ok": true, "result": result }))
+    }
+
+    pub fn error(code: u16, description: &str) -> Self {
+        Self::json(code, json!({ "ok": false, "error_code": code, "description": description }))
+    }
+
+    pub fn retry_after(secs: u64) -> Self {
+        Self::json(429, json!({
+            "ok": false,
+            "error_code": 429,
+            "description": format!("Too Many Requests: retry after {}", secs),
+            "parameters": { "retry_after": secs },
+        }))
+    }
+
+    pub fn migrate_to(chat_id: i64) -> Self {
+        Self::json(400, json!({
+            "ok": false,
+            "error_code": 400,
+            "description": "Bad Request: group chat was upgraded to a supergroup chat",
+            "parameters": { "migrate_to_chat_id": chat_id },
+        }))
+    }
+
+    /// A bare status such as 502 from a proxy in front of the API, without a JSON body.
+    pub fn status(status: u16) -> Self {
+        Self { status, body: String::new(), retry_after_header: None }
+    }
+
+    /// Also sends the HTTP `Retry-After` header.
+    pub fn with_retry_after_header(mut self, secs: u64) -> Self {
+        self.retry_after_header = Some(secs);
+        self
+    }
+
+    fn json(status: u16, body: Value) -> Self {
+        Self { status, body: body.to_string(), retry_after_header: None }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    updates: VecDeque<Value>,
+    next_update_id: i64,
+    next_message_id: i64,
+    calls: Vec<Call>,
+    replies: HashMap<String, VecDeque<Reply>>,
+    files: HashMap<String, Vec<u8>>,
+    /// Polls sent through sendPoll, by chat and message id, for stopPoll.
+    polls: HashMap<(i64, i64), Value>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    updates_ready: Notify,
+    call_received: Notify,
+}
+
+/// In-process stand-in for the Telegram Bot API. It answers the methods the bot
+/// uses with plausible results; tests queue updates for getUpdates, inspect every
+/// call the client made and script error replies (429, 5xx, Telegram errors).
+pub struct MockBotApi {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockBotApi {
+    pub async fn start() -> Self {
+        let incoming = AddrIncoming::bind(&([127, 0, 0, 1], 0).into()).expect("bind mock Bot API");
+        let addr = incoming.local_addr();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { next_update_id: 1, next_message_id: 1, ..Default::default() }),
+            updates_ready: Notify::new(),
+            call_received: Notify::new(),
+        });
+        let svc_shared = shared.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let shared = svc_shared.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(shared.clone(), req))) }
+        });
+        let (tx, rx) = oneshot::channel();
+        let server = Server::builder(incoming).serve(make_svc).with_graceful_shutdown(async {
+            let _ = rx.await;
+        });
+        tokio::spawn(server);
+        Self { addr, shared, shutdown: Some(tx) }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A client pointed at the mock, without rate limiting so tests run at full speed.
+    pub fn client(&self) -> Client {
+        let mut client = Client::builder(MOCK_TOKEN).api_url(self.url()).build();
+        client.rate_limiter = None;
+        client.chat_limiter = None;
+        client
+    }
+
+    /// Queues an update for getUpdates. A missing `update_id` is assigned; the id is returned.
+    pub fn push_update(&self, mut update: Value) -> i64 {
+        let id = {
+            let mut st = self.shared.state.lock().unwrap();
+            let id = match update.get("update_id").and_then(|v| v.as_i64()) {
+                Some(id) => id,
+                None => {
+                    update["update_id"] = json!(st.next_update_id);
+                    st.next_update_id
+                }
+            };
+            st.next_update_id = st.next_update_id.max(id + 1);
+            st.updates.push_back(update);
+            id
+        };
+        self.shared.updates_ready.notify_waiters();
+        id
+    }
+
+    /// Queues a text message from user `from` in `chat_id`.
+    pub fn push_message(&self, chat_id: i64, from: i64, text: &str) -> i64 {
+        let message = self.message_json(chat_id, Some(from), Some(text));
+        self.push_update(json!({ "message": message }))
+    }
+
+    /// Queues a press on an inline button with `data`, attached to a message the bot sent in `chat_id`.
+    pub fn push_callback(&self, chat_id: i64, from: i64, data: &str) -> i64 {
+        let message = self.message_json(chat_id, Some(MOCK_BOT_ID), Some("menu"));
+        let id = format!("cb{}", self.shared.state.lock().unwrap().next_update_id);
+        self.push_update(json!({
+            "callback_query": {
+                "id": id,
+                "from": user_json(from),
+                "message": message,
+                "chat_instance": "mock",
+                "data": data,
+            }
+        }))
+    }
+
+    /// Queues an inline query `@mock_bot query` from user `from`, scrolled to `offset`.
+    pub fn push_inline_query(&self, from: i64, query: &str, offset: &str) -> i64 {
+        let id = format!("iq{}", self.shared.state.lock().unwrap().next_update_id);
+        self.push_update(json!({
+            "inline_query": {
+                "id": id,
+                "from": user_json(from),
+                "query": query,
+                "offset": offset,
+                "chat_type": "sender",
+            }
+        }))
+    }
+
+    /// Queues the pre-checkout query Telegram sends when user `from` presses Pay.
+    pub fn push_pre_checkout(&self, from: i64, payload: &str, currency: &str, total_amount: i64) -> i64 {
+        let id = format!("pc{}", self.shared.state.lock().unwrap().next_update_id);
+        self.push_update(json!({
+            "pre_checkout_query": {
+                "id": id,
+                "from": user_json(from),
+                "currency": currency,
+                "total_amount": total_amount,
+                "invoice_payload": payload,
+            }
+        }))
+    }
+
+    /// Queues the service message for a completed payment by `from` in their private chat.
+    pub fn push_successful_payment(&self, from: i64, payload: &str, currency: &str, total_amount: i64, charge_id: &str) -> i64 {
+        let mut message = self.message_json(from, Some(from), None);
+        message["successful_payment"] = json!({
+            "currency": currency,
+            "total_amount": total_amount,
+            "invoice_payload": payload,
+            "telegram_payment_charge_id": charge_id,
+            "provider_payment_charge_id": "",
+        });
+        self.push_update(json!({ "message": message }))
+    }
+
+    /// Queues `from`'s vote in a non-anonymous poll; empty `option_ids` retracts it.
+    pub fn push_poll_answer(&self, poll_id: &str, from: i64, option_ids: &[u32]) -> i64 {
+        self.push_update(json!({ "poll_answer": { "poll_id": poll_id, "user": user_json(from), "option_ids": option_ids } }))
+    }
+
+    /// Makes the next call to `method` answer with `reply`. Several replies for the
+    /// same method are used in order, then the default behaviour resumes.
+    pub fn enqueue(&self, method: &str, reply: Reply) {
+        let mut st = self.shared.state.lock().unwrap();
+        st.replies.entry(method.to_string()).or_default().push_back(reply);
+    }
+
+    /// Makes `file_id` resolvable by getFile and downloadable from the file endpoint.
+    pub fn add_file(&self, file_id: &str, data: impl Into<Vec<u8>>) {
+        self.shared.state.lock().unwrap().files.insert(file_id.to_string(), data.into());
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.shared.state.lock().unwrap().calls.clone()
+    }
+
+    pub fn calls_to(&self, method: &str) -> Vec<Call> {
+        self.calls().into_iter().filter(|c| c.method == method).collect()
+    }
+
+    /// Waits until `method` has been called at least `count` times and returns those
+    /// calls. Handlers run on spawned tasks, so assertions have to wait for them.
+    pub async fn wait_for(&self, method: &str, count: usize) -> Vec<Call> {
+        let wait = async {
+            loop {
+                let notified = self.shared.call_received.notified();
+                let calls = self.calls_to(method);
+                if calls.len() >= count {
+                    return calls;
+                }
+                notified.await;
+            }
+        };
+        match timeout(WAIT_TIMEOUT, wait).await {
+            Ok(calls) => calls,
+            Err(_) => panic!("timed out waiting for {} call(s) to {}; got {:?}", count, method, self.calls()),
+        }
+    }
+
+    fn message_json(&self, chat_id: i64, from: Option<i64>, text: Option<&str>) -> Value {
+        let mut st = self.shared.state.lock().unwrap();
+        message_json(&mut st, chat_id, from, text)
+    }
+}
+
+impl Drop for MockBotApi {
+    fn drop(&mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+/// A directory for a test's data files, removed when the test ends.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `name` has to be unique among the tests; the process id keeps parallel
+    /// test runs apart.
+    pub fn new(name: &str) -> Self {
+        Self(std::env::temp_dir().join(format!("bot-{}-{}", name, std::process::id())))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Dispatches the pending updates, then confirms them like the polling loop does.
+pub async fn poll(client: &Client, disp: &Dispatcher) {
+    let mut offset = 0;
+    for u in client.get_updates(0, 0, &disp.allowed_updates()).await.unwrap() {
+        offset = u.update_id + 1;
+        disp.dispatch_update(client.clone(), u).await;
+    }
+    client.get_updates(offset, 0, &[]).await.unwrap();
+}
+
+fn user_json(id: i64) -> Value {
+    if id == MOCK_BOT_ID {
+        json!({ "id": id, "is_bot": true, "first_name": "Mock", "username": MOCK_BOT_USERNAME })
+    } else {
+        json!({ "id": id, "is_bot": false, "first_name": format!("User {}", id) })
+    }
+}
+
+fn message_json(st: &mut State, chat_id: i64, from: Option<i64>, text: Option<&str>) -> Value {
+    let message_id = st.next_message_id;
+    st.next_message_id += 1;
+    let kind = if chat_id > 0 { "private" } else { "supergroup" };
+    let mut m = json!({
+        "message_id": message_id,
+        "date": chrono::Utc::now().timestamp(),
+        "chat": { "id": chat_id, "type": kind },
+    });
+    if let Some(from) = from {
+        m["from"] = user_json(from);
+    }
+    if let Some(text) = text {
+        m["text"] = json!(text);
+    }
+    m
+}
+
+fn respond(reply: Reply) -> Response<Body> {
+    let mut resp = Response::new(Body::from(reply.body));
+    *resp.status_mut() = StatusCode::from_u16(reply.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    resp.headers_mut().insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    if let Some(secs) = reply.retry_after_header {
+        resp.headers_mut().insert(hyper::header::RETRY_AFTER, secs.into());
+    }
+    resp
+}
+
+async fn handle(shared: Arc<Shared>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let path = req.uri().path().to_string();
+
+    if let Some(rest) = path.strip_prefix("/file/bot") {
+        let file_path = rest.split_once('/').map(|(_, p)| p).unwrap_or("");
+        let file_id = file_path.rsplit('/').next().unwrap_or("");
+        let data = shared.state.lock().unwrap().files.get(file_id).cloned();
+        return Ok(match data {
+            Some(data) => Response::new(Body::from(data)),
+            None => respond(Reply::status(404)),
+        });
+    }
+
+    let method = match path.strip_prefix("/bot").and_then(|r| r.split_once('/')) {
+        Some((token, method)) if token == MOCK_TOKEN => method.to_string(),
+        Some(_) => return Ok(respond(Reply::error(401, "Unauthorized"))),
+        None => return Ok(respond(Reply::error(404, "Not Found"))),
+    };
+    if req.method() != Method::POST && req.method() != Method::GET {
+        return Ok(respond(Reply::error(405, "Method Not Allowed")));
+    }
+
+    let (params, files) = match read_params(req).await {
+        Ok(p) => p,
+        Err(e) => return Ok(respond(Reply::error(400, &format!("Bad Request: {}", e)))),
+    };
+    let call = Call { method: method.clone(), params, files };
+
+    let reply = {
+        let mut st = shared.state.lock().unwrap();
+        st.calls.push(call.clone());
+        match st.replies.get_mut(&method).and_then(|q| q.pop_front()) {
+            Some(reply) => Some(reply),
+            None if method == "getUpdates" => None,
+            None => Some(default_reply(&mut st, &call)),
+        }
+    };
+    shared.call_received.notify_waiters();
+
+    let reply = match reply {
+        Some(r) => r,
+        None => get_updates(&shared, &call).await,
+    };
+    Ok(respond(reply))
+}
+
+async fn read_params(req: Request<Body>) -> Result<(Value, Vec<UploadedFile>), String> {
+    let content_type = req.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+
+    if content_type.starts_with("multipart/form-data") {
+        let boundary = multer::parse_boundary(&content_type).map_err(|e| e.to_string())?;
+        let body = hyper::body::to_bytes(req.into_body()).await.map_err(|e| e.to_string())?;
+        let stream = futures::stream::once(async move { Ok::<_, Infallible>(body) });
+        let mut multipart = multer::Multipart::new(stream, boundary);
+        let mut params = Map::new();
+        let mut files = Vec::new();
+        while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+            let name = field.name().unwrap_or("").to_string();
+            match field.file_name().map(|f| f.to_string()) {
+                Some(filename) => {
+                    let data = field.bytes().await.map_err(|e| e.to_string())?.to_vec();
+                    files.push(UploadedFile { field: name, filename: Some(filename), data });
+                }
+                None => {
+                    let text = field.text().await.map_err(|e| e.to_string())?;
+                    params.insert(name, Value::String(text));
+                }
+            }
+        }
+        return Ok((Value::Object(params), files));
+    }
+
+    let body = hyper::body::to_bytes(req.into_body()).await.map_err(|e| e.to_string())?;
+    if body.is_empty() {
+        return Ok((json!({}), Vec::new()));
+    }
+    let params = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+    Ok((params, Vec::new()))
+}
+
+/// Answers getUpdates like the real long poll: confirmed updates (below `offset`)
+/// are dropped, and an empty queue is held open for up to `timeout` seconds.
+async fn get_updates(shared: &Shared, call: &Call) -> Reply {
+    let offset = call.i64("offset").unwrap_or(0);
+    let wait = Duration::from_secs(call.i64("timeout").unwrap_or(0).max(0) as u64);
+    let take = || {
+        let mut st = shared.state.lock().unwrap();
+        st.updates.retain(|u| u["update_id"].as_i64().unwrap_or(0) >= offset);
+        st.updates.iter().cloned().collect::<Vec<_>>()
+    };
+    let notified = shared.updates_ready.notified();
+    let mut updates = take();
+    if updates.is_empty() && !wait.is_zero() {
+        let _ = timeout(wait, notified).await;
+        updates = take();
+    }
+    Reply::ok(Value::Array(updates))
+}
+
+/// The result a well-behaved Bot API would return for `call`.
+fn default_reply(st: &mut State, call: &Call) -> Reply {
+    let chat_id = call.i64("chat_id").unwrap_or(0);
+    match call.method.as_str() {
+        "getMe" => Reply::ok(json!({
+            "id": MOCK_BOT_ID,
+            "is_bot": true,
+            "first_name": "Mock",
+            "username": MOCK_BOT_USERNAME,
+            "can_join_groups": true,
+            "can_read_all_group_messages": false,
+            "supports_inline_queries": false,
+        })),
+        "sendMessage" => Reply::ok(message_json(st, chat_id, Some(MOCK_BOT_ID), call.str("text"))),
+        "sendInvoice" => {
+            let mut m = message_json(st, chat_id, Some(MOCK_BOT_ID), None);
+            let total: i64 = call.params["prices"].as_array().into_iter().flatten().filter_map(|p| p["amount"].as_i64()).sum();
+            m["invoice"] = json!({
+                "title": call.str("title"),
+                "description": call.str("description"),
+                "start_parameter": call.str("start_parameter").unwrap_or(""),
+                "currency": call.str("currency"),
+                "total_amount": total,
+            });
+            Reply::ok(m)
+        }
+        "sendPoll" => {
+            let mut m = message_json(st, chat_id, Some(MOCK_BOT_ID), None);
+            let options: Vec<Value> = call.params["options"].as_array().into_iter().flatten().map(|o| json!({ "text": o["text"], "voter_count": 0 })).collect();
+            let poll = json!({
+                "id": format!("poll{}", st.polls.len() + 1),
+                "question": call.str("question"),
+                "options": options,
+                "total_voter_count": 0,
+                "is_closed": call.params["is_closed"].as_bool().unwrap_or(false),
+                "is_anonymous": call.params["is_anonymous"].as_bool().unwrap_or(true),
+                "type": call.str("type").unwrap_or("regular"),
+                "allows_multiple_answers": call.params["allows_multiple_answers"].as_bool().unwrap_or(false),
+                "correct_option_id": call.params.get("correct_option_id"),
+            });
+            st.polls.insert((chat_id, m["message_id"].as_i64().unwrap_or(0)), poll.clone());
+            m["poll"] = poll;
+            Reply::ok(m)
+        }
+        "stopPoll" => match st.polls.get_mut(&(chat_id, call.i64("message_id").unwrap_or(0))) {
+            Some(poll) if poll["is_closed"] == true => Reply::error(400, "Bad Request: poll has already been closed"),
+            Some(poll) => {
+                poll["is_closed"] = json!(true);
+                Reply::ok(poll.clone())
+            }
+            None => Reply::error(400, "Bad Request: message with poll to stop not found"),
+        },
+        "createInvoiceLink" => Reply::ok(json!(format!("https://t.me/$mock_invoice_{}", call.str("payload").unwrap_or("")))),
+        "editMessageText" | "editMessageCaption" | "editMessageReplyMarkup" => {
+            if call.params.get("inline_message_id").is_some() {
+                return Reply::ok(json!(true));
+            }
+            let mut m = message_json(st, chat_id, Some(MOCK_BOT_ID), call.str("text"));
+            if let Some(id) = call.i64("message_id") {
+                m["message_id"] = json!(id);
+            }
+            Reply::ok(m)
+        }
+        "sendDocument" | "sendPhoto" | "sendAudio" | "sendVoice" | "sendVideo" | "sendAnimation" | "sendVideoNote" | "sendSticker" => {
+            let field = media_field(&call.method);
+            let mut m = message_json(st, chat_id, Some(MOCK_BOT_ID), None);
+            let (file_id, size) = match call.files.iter().find(|f| f.field == field) {
+                Some(upload) => {
+                    let id = format!("file{}", st.files.len() + 1);
+                    st.files.insert(id.clone(), upload.data.clone());
+                    (id, Some(upload.data.len()))
+                }
+                None => (call.str(field).unwrap_or("").to_string(), None),
+            };
+            m[field] = media_json(field, &file_id, size);
+            if let Some(caption) = call.str("caption") {
+                m["caption"] = json!(caption);
+            }
+            Reply::ok(m)
+        }
+        "getFile" => {
+            let file_id = call.str("file_id").unwrap_or("");
+            match st.files.get(file_id) {
+                Some(data) => Reply::ok(json!({
+                    "file_id": file_id,
+                    "file_unique_id": file_id,
+                    "file_size": data.len(),
+                    "file_path": format!("documents/{}", file_id),
+                })),
+                None => Reply::error(400, "Bad Request: invalid file_id"),
+            }
+        }
+        "getChat" => Reply::ok(json!({ "id": chat_id, "type": if chat_id > 0 { "private" } else { "supergroup" } })),
+        "getUserProfilePhotos" => Reply::ok(json!({ "total_count": 0, "photos": [] })),
+        "getWebhookInfo" => Reply::ok(json!({ "url": "", "has_custom_certificate": false, "pending_update_count": 0 })),
+        "getMyCommands" => Reply::ok(json!([])),
+        _ => Reply::ok(json!(true)),
+    }
+}
+
+/// The object Telegram returns in `field` of a sent message, with the fields
+/// each type requires.
+fn media_json(field: &str, file_id: &str, size: Option<usize>) -> Value {
+    let mut file = json!({ "file_id": file_id, "file_unique_id": format!("u{}", file_id) });
+    if let Some(size) = size {
+        file["file_size"] = json!(size);
+    }
+    let extra = match field {
+        // Telegram sends every size it made, smallest first; the original is the last one.
+        "photo" => {
+            let mut thumb = json!({ "file_id": format!("{}_s", file_id), "file_unique_id": format!("u{}_s", file_id), "width": 90, "height": 60 });
+            thumb["file_size"] = json!(1024);
+            file["width"] = json!(1280);
+            file["height"] = json!(853);
+            return json!([thumb, file]);
+        }
+        "audio" => json!({ "duration": 180, "mime_type": "audio/mpeg" }),
+        "voice" => json!({ "duration": 5, "mime_type": "audio/ogg" }),
+        "video" | "animation" => json!({ "width": 640, "height": 360, "duration": 10, "mime_type": "video/mp4" }),
+        "video_note" => json!({ "length": 240, "duration": 10 }),
+        "sticker" => json!({ "type": "regular", "width": 512, "height": 512, "is_animated": false, "is_video": false }),
+        _ => json!({}),
+    };
+    if let (Some(file), Value::Object(extra)) = (file.as_object_mut(), extra) {
+        file.extend(extra);
+    }
+    file
+}
+
+fn media_field(method: &str) -> &'static str {
+    match method {
+        "sendPhoto" => "photo",
+        "sendAudio" => "audio",
+        "sendVoice" => "voice",
+        "sendVideo" => "video",
+        "sendAnimation" => "animation",
+        "sendVideoNote" => "video_note",
+        "sendSticker" => "sticker",
+        _ => "document",
+    }
+}
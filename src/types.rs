@@ -1,30 +1,555 @@
-use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 
 #[derive(Deserialize, Serialize)]
 pub struct ApiResponse<T> {
     pub ok: bool,
-    pub result: T,
+    #[serde(default)]
+    pub result: Option<T>,
     pub description: Option<String>,
+    pub error_code: Option<i32>,
+    pub parameters: Option<ResponseParameters>,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Serialize, Debug)]
-pub struct Update {
-    pub update_id: i64,
-    pub message: Option<Message>,
-    pub edited_message: Option<Message>,
-    pub callback_query: Option<CallbackQuery>,
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseParameters {
+    pub migrate_to_chat_id: Option<i64>,
+    pub retry_after: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Fields of a Bot API object that its struct does not declare. They are kept
+/// so that serializing a value gives back what Telegram sent, including fields
+/// added in Bot API releases newer than this code.
+pub type Extra = serde_json::Map<String, Value>;
+
+// Declares `Update` with one optional field per kind. Deserializing keeps the
+// raw JSON alongside the typed fields, and serializing writes it back unchanged.
+macro_rules! update {
+    ($($field:ident: $ty:ty,)*) => {
+        #[allow(dead_code)]
+        #[derive(Debug, Clone)]
+        pub struct Update {
+            pub update_id: i64,
+            $(pub $field: Option<$ty>,)*
+            pub extra: Extra,
+            raw: Arc<Value>,
+        }
+
+        #[derive(Deserialize)]
+        struct UpdateFields {
+            update_id: i64,
+            $($field: Option<$ty>,)*
+            #[serde(flatten)]
+            extra: Extra,
+        }
+
+        impl<'de> Deserialize<'de> for Update {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let raw = Value::deserialize(deserializer)?;
+                let f = UpdateFields::deserialize(&raw).map_err(de::Error::custom)?;
+                let mut update = Update { update_id: f.update_id, $($field: f.$field,)* extra: f.extra, raw: Arc::new(raw) };
+                update.share_raw();
+                Ok(update)
+            }
+        }
+    };
+}
+
+update! {
+    message: Message,
+    edited_message: Message,
+    channel_post: Message,
+    edited_channel_post: Message,
+    inline_query: InlineQuery,
+    chosen_inline_result: ChosenInlineResult,
+    callback_query: CallbackQuery,
+    shipping_query: ShippingQuery,
+    pre_checkout_query: PreCheckoutQuery,
+    poll: Poll,
+    poll_answer: PollAnswer,
+    my_chat_member: ChatMemberUpdated,
+    chat_member: ChatMemberUpdated,
+    chat_join_request: ChatJoinRequest,
+    message_reaction: MessageReactionUpdated,
+}
+
+impl Serialize for Update {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw.serialize(serializer)
+    }
+}
+
+/// The kinds of update, named as in `allowed_updates`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateKind {
+    Message,
+    EditedMessage,
+    ChannelPost,
+    EditedChannelPost,
+    InlineQuery,
+    ChosenInlineResult,
+    CallbackQuery,
+    ShippingQuery,
+    PreCheckoutQuery,
+    Poll,
+    PollAnswer,
+    MyChatMember,
+    ChatMember,
+    ChatJoinRequest,
+    MessageReaction,
+}
+
+impl UpdateKind {
+    pub const ALL: [UpdateKind; 15] = [
+        UpdateKind::Message,
+        UpdateKind::EditedMessage,
+        UpdateKind::ChannelPost,
+        UpdateKind::EditedChannelPost,
+        UpdateKind::InlineQuery,
+        UpdateKind::ChosenInlineResult,
+        UpdateKind::CallbackQuery,
+        UpdateKind::ShippingQuery,
+        UpdateKind::PreCheckoutQuery,
+        UpdateKind::Poll,
+        UpdateKind::PollAnswer,
+        UpdateKind::MyChatMember,
+        UpdateKind::ChatMember,
+        UpdateKind::ChatJoinRequest,
+        UpdateKind::MessageReaction,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpdateKind::Message => "message",
+            UpdateKind::EditedMessage => "edited_message",
+            UpdateKind::ChannelPost => "channel_post",
+            UpdateKind::EditedChannelPost => "edited_channel_post",
+            UpdateKind::InlineQuery => "inline_query",
+            UpdateKind::ChosenInlineResult => "chosen_inline_result",
+            UpdateKind::CallbackQuery => "callback_query",
+            UpdateKind::ShippingQuery => "shipping_query",
+            UpdateKind::PreCheckoutQuery => "pre_checkout_query",
+            UpdateKind::Poll => "poll",
+            UpdateKind::PollAnswer => "poll_answer",
+            UpdateKind::MyChatMember => "my_chat_member",
+            UpdateKind::ChatMember => "chat_member",
+            UpdateKind::ChatJoinRequest => "chat_join_request",
+            UpdateKind::MessageReaction => "message_reaction",
+        }
+    }
+}
+
+impl std::str::FromStr for UpdateKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        UpdateKind::ALL.into_iter().find(|k| k.as_str() == s).ok_or_else(|| format!("unknown update kind: {}", s))
+    }
+}
+
+impl Update {
+    /// The update exactly as Telegram sent it, including kinds and fields that
+    /// the typed structs do not model.
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+
+    // Lets each message find its own JSON in `raw`.
+    fn share_raw(&mut self) {
+        let raw = self.raw.clone();
+        let messages = [
+            (&mut self.message, "message"),
+            (&mut self.edited_message, "edited_message"),
+            (&mut self.channel_post, "channel_post"),
+            (&mut self.edited_channel_post, "edited_channel_post"),
+        ];
+        for (msg, field) in messages {
+            if let Some(msg) = msg {
+                msg.source = Some((raw.clone(), field));
+            }
+        }
+    }
+
+    /// Which field of the update is set; `None` for kinds this bot does not model.
+    pub fn kind(&self) -> Option<UpdateKind> {
+        let set = [
+            (self.message.is_some(), UpdateKind::Message),
+            (self.edited_message.is_some(), UpdateKind::EditedMessage),
+            (self.channel_post.is_some(), UpdateKind::ChannelPost),
+            (self.edited_channel_post.is_some(), UpdateKind::EditedChannelPost),
+            (self.inline_query.is_some(), UpdateKind::InlineQuery),
+            (self.chosen_inline_result.is_some(), UpdateKind::ChosenInlineResult),
+            (self.callback_query.is_some(), UpdateKind::CallbackQuery),
+            (self.shipping_query.is_some(), UpdateKind::ShippingQuery),
+            (self.pre_checkout_query.is_some(), UpdateKind::PreCheckoutQuery),
+            (self.poll.is_some(), UpdateKind::Poll),
+            (self.poll_answer.is_some(), UpdateKind::PollAnswer),
+            (self.my_chat_member.is_some(), UpdateKind::MyChatMember),
+            (self.chat_member.is_some(), UpdateKind::ChatMember),
+            (self.chat_join_request.is_some(), UpdateKind::ChatJoinRequest),
+            (self.message_reaction.is_some(), UpdateKind::MessageReaction),
+        ];
+        set.into_iter().find(|(is_set, _)| *is_set).map(|(_, kind)| kind)
+    }
 }
 
+/// Optional objects other than the small, common ones are boxed. serde's derived
+/// visitor keeps every field on the stack while it parses; with them inline,
+/// parsing one message in a debug build took up to 512 KB, and a reply or pinned
+/// message inside it as much again, which overflowed 2 MB test threads.
 #[allow(dead_code)]
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Message {
     pub message_id: i64,
-    pub text: Option<String>,
+    pub message_thread_id: Option<i64>,
+    pub date: Option<i64>,
+    pub edit_date: Option<i64>,
     pub chat: Chat,
     pub from: Option<User>,
-    pub contact: Option<Contact>,
+    /// Set instead of `from` for messages sent on behalf of a chat.
+    pub sender_chat: Option<Box<Chat>>,
+    pub forward_origin: Option<Box<MessageOrigin>>,
+    pub is_topic_message: Option<bool>,
+    pub is_automatic_forward: Option<bool>,
+    pub reply_to_message: Option<Box<Message>>,
+    pub via_bot: Option<Box<User>>,
+    pub media_group_id: Option<String>,
+    pub author_signature: Option<String>,
+    pub text: Option<String>,
+    pub entities: Option<Vec<MessageEntity>>,
+    pub caption: Option<String>,
+    pub caption_entities: Option<Vec<MessageEntity>>,
+    pub photo: Option<Vec<PhotoSize>>,
+    pub document: Option<Box<Document>>,
+    pub audio: Option<Box<Audio>>,
+    pub voice: Option<Box<Voice>>,
+    pub video: Option<Box<Video>>,
+    pub video_note: Option<Box<VideoNote>>,
+    pub animation: Option<Box<Animation>>,
+    pub sticker: Option<Box<Sticker>>,
+    pub contact: Option<Box<Contact>>,
     pub location: Option<Location>,
+    pub venue: Option<Box<Venue>>,
+    pub poll: Option<Box<Poll>>,
+    pub dice: Option<Dice>,
+    pub new_chat_members: Option<Vec<User>>,
+    pub left_chat_member: Option<Box<User>>,
+    pub new_chat_title: Option<String>,
+    pub new_chat_photo: Option<Vec<PhotoSize>>,
+    pub delete_chat_photo: Option<bool>,
+    pub group_chat_created: Option<bool>,
+    pub supergroup_chat_created: Option<bool>,
+    pub channel_chat_created: Option<bool>,
+    pub migrate_to_chat_id: Option<i64>,
+    pub migrate_from_chat_id: Option<i64>,
+    pub pinned_message: Option<Box<Message>>,
+    pub invoice: Option<Box<Invoice>>,
+    pub successful_payment: Option<Box<SuccessfulPayment>>,
+    pub refunded_payment: Option<Box<RefundedPayment>>,
+    pub reply_markup: Option<Box<InlineKeyboardMarkup>>,
+    #[serde(flatten)]
+    pub extra: Extra,
+    /// The update the message arrived in and the field holding it; unset for
+    /// messages parsed on their own, such as API results.
+    #[serde(skip)]
+    source: Option<(Arc<Value>, &'static str)>,
+}
+
+/// What a message carries, for handlers that react to more than commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Text,
+    Photo,
+    Document,
+    Audio,
+    Voice,
+    Video,
+    VideoNote,
+    Animation,
+    Sticker,
+    Contact,
+    Location,
+    Venue,
+    Poll,
+    Dice,
+    Invoice,
+    SuccessfulPayment,
+    RefundedPayment,
+    /// Members joined, left, or the chat title, photo or type changed.
+    Service,
+    Other,
+}
+
+#[allow(dead_code)]
+impl Message {
+    /// The message exactly as Telegram sent it, when it arrived in an update.
+    pub fn raw(&self) -> Option<&Value> {
+        self.source.as_ref().map(|(update, field)| &update[*field])
+    }
+
+    /// For the service messages Telegram sends when a group becomes a supergroup,
+    /// returns `(old_chat_id, new_chat_id)`.
+    pub fn chat_migration(&self) -> Option<(i64, i64)> {
+        if let Some(to) = self.migrate_to_chat_id {
+            return Some((self.chat.id, to));
+        }
+        self.migrate_from_chat_id.map(|from| (from, self.chat.id))
+    }
+
+    pub fn kind(&self) -> MessageKind {
+        // A venue also carries its location, so it is checked first.
+        if self.venue.is_some() {
+            MessageKind::Venue
+        } else if self.text.is_some() {
+            MessageKind::Text
+        } else if self.photo.is_some() {
+            MessageKind::Photo
+        } else if self.animation.is_some() {
+            // Animations come with a `document` too.
+            MessageKind::Animation
+        } else if self.document.is_some() {
+            MessageKind::Document
+        } else if self.audio.is_some() {
+            MessageKind::Audio
+        } else if self.voice.is_some() {
+            MessageKind::Voice
+        } else if self.video.is_some() {
+            MessageKind::Video
+        } else if self.video_note.is_some() {
+            MessageKind::VideoNote
+        } else if self.sticker.is_some() {
+            MessageKind::Sticker
+        } else if self.contact.is_some() {
+            MessageKind::Contact
+        } else if self.location.is_some() {
+            MessageKind::Location
+        } else if self.poll.is_some() {
+            MessageKind::Poll
+        } else if self.dice.is_some() {
+            MessageKind::Dice
+        } else if self.invoice.is_some() {
+            MessageKind::Invoice
+        } else if self.successful_payment.is_some() {
+            MessageKind::SuccessfulPayment
+        } else if self.refunded_payment.is_some() {
+            MessageKind::RefundedPayment
+        } else if self.is_service() {
+            MessageKind::Service
+        } else {
+            MessageKind::Other
+        }
+    }
+
+    fn is_service(&self) -> bool {
+        self.new_chat_members.is_some()
+            || self.left_chat_member.is_some()
+            || self.new_chat_title.is_some()
+            || self.new_chat_photo.is_some()
+            || self.delete_chat_photo.is_some()
+            || self.group_chat_created.is_some()
+            || self.supergroup_chat_created.is_some()
+            || self.channel_chat_created.is_some()
+            || self.migrate_to_chat_id.is_some()
+            || self.migrate_from_chat_id.is_some()
+            || self.pinned_message.is_some()
+    }
+
+    /// The text of a text message, or the caption of a media message. Commands
+    /// are read from here, so `/echo hi` works as a photo caption too.
+    pub fn text_or_caption(&self) -> Option<&str> {
+        self.text.as_deref().or(self.caption.as_deref())
+    }
+
+    /// Entities of whichever of text or caption `text_or_caption` returned.
+    pub fn text_or_caption_entities(&self) -> &[MessageEntity] {
+        let entities = if self.text.is_some() { &self.entities } else { &self.caption_entities };
+        entities.as_deref().unwrap_or_default()
+    }
+
+    /// The largest size of a photo message.
+    pub fn largest_photo(&self) -> Option<&PhotoSize> {
+        self.photo.as_ref()?.iter().max_by_key(|p| p.width.unwrap_or(0) * p.height.unwrap_or(0))
+    }
+
+    /// The `file_id` of the file the message carries, if any.
+    pub fn file_id(&self) -> Option<&str> {
+        if let Some(p) = self.largest_photo() {
+            return Some(&p.file_id);
+        }
+        let ids = [
+            self.animation.as_ref().map(|f| &f.file_id),
+            self.document.as_ref().map(|f| &f.file_id),
+            self.audio.as_ref().map(|f| &f.file_id),
+            self.voice.as_ref().map(|f| &f.file_id),
+            self.video.as_ref().map(|f| &f.file_id),
+            self.video_note.as_ref().map(|f| &f.file_id),
+            self.sticker.as_ref().map(|f| &f.file_id),
+        ];
+        ids.into_iter().flatten().next().map(|s| s.as_str())
+    }
+}
+
+/// A special part of a message text: a command, mention, link, formatting...
+/// Offsets and lengths are in UTF-16 code units.
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MessageEntity {
+    /// `bot_command`, `mention`, `url`, `bold`, `text_link`, `pre`, ...
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub offset: usize,
+    pub length: usize,
+    pub url: Option<String>,
+    pub user: Option<User>,
+    pub language: Option<String>,
+    pub custom_emoji_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Where a forwarded message originally came from.
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageOrigin {
+    User { date: i64, sender_user: User, #[serde(flatten)] extra: Extra },
+    HiddenUser { date: i64, sender_user_name: String, #[serde(flatten)] extra: Extra },
+    Chat { date: i64, sender_chat: Chat, author_signature: Option<String>, #[serde(flatten)] extra: Extra },
+    Channel { date: i64, chat: Chat, message_id: i64, author_signature: Option<String>, #[serde(flatten)] extra: Extra },
+    /// An origin type added to the Bot API after this code was written, kept
+    /// as sent so that it serializes back unchanged.
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Document {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub thumbnail: Option<PhotoSize>,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Audio {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub duration: u32,
+    pub performer: Option<String>,
+    pub title: Option<String>,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<u64>,
+    pub thumbnail: Option<PhotoSize>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Voice {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub duration: u32,
+    pub mime_type: Option<String>,
+    pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Video {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub duration: u32,
+    pub thumbnail: Option<PhotoSize>,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct VideoNote {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub length: u32,
+    pub duration: u32,
+    pub thumbnail: Option<PhotoSize>,
+    pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Animation {
+    pub file_id: String,
+    pub file_unique_id: String,
+    pub width: u32,
+    pub height: u32,
+    pub duration: u32,
+    pub thumbnail: Option<PhotoSize>,
+    pub file_name: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Sticker {
+    pub file_id: String,
+    pub file_unique_id: String,
+    /// `regular`, `mask` or `custom_emoji`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub width: u32,
+    pub height: u32,
+    pub is_animated: bool,
+    pub is_video: bool,
+    pub emoji: Option<String>,
+    pub set_name: Option<String>,
+    pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Venue {
+    pub location: Location,
+    pub title: String,
+    pub address: String,
+    pub foursquare_id: Option<String>,
+    pub google_place_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Dice {
+    pub emoji: String,
+    pub value: u32,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -33,9 +558,31 @@ pub struct Chat {
     pub id: i64,
     #[serde(rename = "type")]
     pub kind: Option<String>,
+    pub title: Option<String>,
     pub username: Option<String>,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
+    pub is_forum: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatFullInfo {
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub title: Option<String>,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub is_forum: Option<bool>,
+    pub bio: Option<String>,
+    pub description: Option<String>,
+    pub invite_link: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -44,7 +591,14 @@ pub struct User {
     pub id: i64,
     pub is_bot: bool,
     pub first_name: String,
+    pub last_name: Option<String>,
     pub username: Option<String>,
+    pub language_code: Option<String>,
+    pub can_join_groups: Option<bool>,
+    pub can_read_all_group_messages: Option<bool>,
+    pub supports_inline_queries: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -55,6 +609,8 @@ pub struct Contact {
     pub last_name: Option<String>,
     pub user_id: Option<i64>,
     pub vcard: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -66,6 +622,8 @@ pub struct Location {
     pub live_period: Option<i64>,
     pub heading: Option<i64>,
     pub proximity_alert_radius: Option<i64>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -74,7 +632,298 @@ pub struct CallbackQuery {
     pub id: String,
     pub from: User,
     pub message: Option<Message>,
+    pub inline_message_id: Option<String>,
+    pub chat_instance: Option<String>,
     pub data: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct InlineQuery {
+    pub id: String,
+    pub from: User,
+    pub query: String,
+    pub offset: String,
+    pub chat_type: Option<String>,
+    pub location: Option<Location>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChosenInlineResult {
+    pub result_id: String,
+    pub from: User,
+    pub location: Option<Location>,
+    pub inline_message_id: Option<String>,
+    pub query: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ShippingAddress {
+    pub country_code: String,
+    pub state: String,
+    pub city: String,
+    pub street_line1: String,
+    pub street_line2: String,
+    pub post_code: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ShippingQuery {
+    pub id: String,
+    pub from: User,
+    pub invoice_payload: String,
+    pub shipping_address: ShippingAddress,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct OrderInfo {
+    pub name: Option<String>,
+    pub phone_number: Option<String>,
+    pub email: Option<String>,
+    pub shipping_address: Option<ShippingAddress>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PreCheckoutQuery {
+    pub id: String,
+    pub from: User,
+    pub currency: String,
+    /// In the smallest units of the currency (cents, or whole Stars for `XTR`).
+    pub total_amount: i64,
+    pub invoice_payload: String,
+    pub shipping_option_id: Option<String>,
+    pub order_info: Option<OrderInfo>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Invoice {
+    pub title: String,
+    pub description: String,
+    pub start_parameter: String,
+    pub currency: String,
+    pub total_amount: i64,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SuccessfulPayment {
+    pub currency: String,
+    pub total_amount: i64,
+    pub invoice_payload: String,
+    pub subscription_expiration_date: Option<i64>,
+    pub is_recurring: Option<bool>,
+    pub is_first_recurring: Option<bool>,
+    pub shipping_option_id: Option<String>,
+    pub order_info: Option<OrderInfo>,
+    /// What `refundStarPayment` takes to refund a Stars payment.
+    pub telegram_payment_charge_id: String,
+    pub provider_payment_charge_id: String,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RefundedPayment {
+    pub currency: String,
+    pub total_amount: i64,
+    pub invoice_payload: String,
+    pub telegram_payment_charge_id: String,
+    pub provider_payment_charge_id: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// One line of an invoice; `amount` is in the smallest units of the currency.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LabeledPrice {
+    pub label: String,
+    pub amount: i64,
+}
+
+#[allow(dead_code)]
+impl LabeledPrice {
+    pub fn new(label: impl Into<String>, amount: i64) -> Self {
+        Self { label: label.into(), amount }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ShippingOption {
+    pub id: String,
+    pub title: String,
+    pub prices: Vec<LabeledPrice>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PollOption {
+    pub text: String,
+    pub voter_count: u32,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Poll {
+    pub id: String,
+    pub question: String,
+    pub options: Vec<PollOption>,
+    pub total_voter_count: u32,
+    pub is_closed: bool,
+    pub is_anonymous: bool,
+    /// `regular` or `quiz`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub allows_multiple_answers: bool,
+    pub correct_option_id: Option<u32>,
+    pub explanation: Option<String>,
+    pub open_period: Option<u32>,
+    pub close_date: Option<i64>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// `type` of a poll being sent.
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PollKind {
+    Regular,
+    Quiz,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct InputPollOption {
+    pub text: String,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PollAnswer {
+    pub poll_id: String,
+    /// Set instead of `user` when the vote was cast on behalf of a chat.
+    pub voter_chat: Option<Chat>,
+    pub user: Option<User>,
+    /// Empty when the vote was retracted.
+    pub option_ids: Vec<u32>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// A chat member and their status: `creator`, `administrator`, `member`,
+/// `restricted`, `left` or `kicked`.
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatMember {
+    pub status: String,
+    pub user: User,
+    pub until_date: Option<i64>,
+    pub custom_title: Option<String>,
+    pub is_anonymous: Option<bool>,
+    pub is_member: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatInviteLink {
+    pub invite_link: String,
+    pub creator: User,
+    pub creates_join_request: bool,
+    pub is_primary: bool,
+    pub is_revoked: bool,
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatMemberUpdated {
+    pub chat: Chat,
+    pub from: User,
+    pub date: i64,
+    pub old_chat_member: ChatMember,
+    pub new_chat_member: ChatMember,
+    pub invite_link: Option<ChatInviteLink>,
+    pub via_join_request: Option<bool>,
+    pub via_chat_folder_invite_link: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ChatJoinRequest {
+    pub chat: Chat,
+    pub from: User,
+    pub user_chat_id: i64,
+    pub date: i64,
+    pub bio: Option<String>,
+    pub invite_link: Option<ChatInviteLink>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReactionType {
+    Emoji { emoji: String, #[serde(flatten)] extra: Extra },
+    CustomEmoji { custom_emoji_id: String, #[serde(flatten)] extra: Extra },
+    Paid { #[serde(flatten)] extra: Extra },
+    /// A reaction type added to the Bot API after this code was written, kept
+    /// as sent so that it serializes back unchanged.
+    #[serde(untagged)]
+    Unknown(Value),
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MessageReactionUpdated {
+    pub chat: Chat,
+    pub message_id: i64,
+    pub user: Option<User>,
+    pub actor_chat: Option<Chat>,
+    pub date: i64,
+    pub old_reaction: Vec<ReactionType>,
+    pub new_reaction: Vec<ReactionType>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Result of an edit: the edited message, or `true` for inline messages.
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum EditResult {
+    Message(Box<Message>),
+    Inline(bool),
 }
 
 #[allow(dead_code)]
@@ -84,6 +933,23 @@ pub struct File {
     pub file_unique_id: String,
     pub file_size: Option<u64>,
     pub file_path: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WebhookInfo {
+    pub url: String,
+    pub has_custom_certificate: bool,
+    pub pending_update_count: u64,
+    pub ip_address: Option<String>,
+    pub last_error_date: Option<i64>,
+    pub last_error_message: Option<String>,
+    pub max_connections: Option<u32>,
+    pub allowed_updates: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -94,6 +960,8 @@ pub struct PhotoSize {
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub file_size: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[allow(dead_code)]
@@ -101,6 +969,8 @@ pub struct PhotoSize {
 pub struct UserProfilePhotos {
     pub total_count: u64,
     pub photos: Vec<Vec<PhotoSize>>, 
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -121,18 +991,22 @@ pub struct ReplyKeyboardMarkup {
     pub resize_keyboard: Option<bool>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct InlineKeyboardButton {
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub callback_data: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub url: Option<String>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct InlineKeyboardMarkup {
     pub inline_keyboard: Vec<Vec<InlineKeyboardButton>>,
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -142,3 +1016,356 @@ pub enum ReplyMarkup {
     InlineKeyboard(InlineKeyboardMarkup),
 }
 
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    #[serde(rename = "HTML")]
+    Html,
+    MarkdownV2,
+    Markdown,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct LinkPreviewOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_disabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefer_small_media: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefer_large_media: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub show_above_text: Option<bool>,
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct BotCommand {
+    pub command: String,
+    pub description: String,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotCommandScope {
+    Default,
+    AllPrivateChats,
+    AllGroupChats,
+    AllChatAdministrators,
+    Chat { chat_id: i64 },
+    ChatAdministrators { chat_id: i64 },
+    ChatMember { chat_id: i64, user_id: i64 },
+}
+
+/// Content of the message sent on the user's behalf when they pick an inline result.
+#[allow(dead_code)]
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum InputMessageContent {
+    Text {
+        message_text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parse_mode: Option<ParseMode>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        link_preview_options: Option<LinkPreviewOptions>,
+    },
+}
+
+#[allow(dead_code)]
+impl InputMessageContent {
+    pub fn text(message_text: impl Into<String>) -> Self {
+        InputMessageContent::Text { message_text: message_text.into(), parse_mode: None, link_preview_options: None }
+    }
+}
+
+/// Declares an inline query result type: `id`, the required fields, then the
+/// optional ones, each with a setter. `$variant` is its `InlineQueryResult` case.
+macro_rules! inline_result {
+    ($name:ident, $variant:ident, { $($field:ident: $fty:ty),* $(,)? }, { $($opt:ident: $oty:ty),* $(,)? }) => {
+        #[derive(Serialize, Debug, Clone)]
+        pub struct $name {
+            pub id: String,
+            $(pub $field: $fty,)*
+            $(
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub $opt: Option<$oty>,
+            )*
+        }
+
+        #[allow(dead_code)]
+        impl $name {
+            pub fn new(id: impl Into<String>, $($field: impl Into<$fty>),*) -> Self {
+                Self { id: id.into(), $($field: $field.into(),)* $($opt: None,)* }
+            }
+
+            $(
+                pub fn $opt(mut self, $opt: impl Into<$oty>) -> Self {
+                    self.$opt = Some($opt.into());
+                    self
+                }
+            )*
+        }
+
+        impl From<$name> for InlineQueryResult {
+            fn from(r: $name) -> Self {
+                InlineQueryResult::$variant(r)
+            }
+        }
+    };
+}
+
+/// One result of an inline query. Cached variants send a file already on
+/// Telegram's servers by `file_id` and share the `type` of their URL counterpart.
+#[allow(dead_code)]
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InlineQueryResult {
+    Article(InlineQueryResultArticle),
+    Photo(InlineQueryResultPhoto),
+    Document(InlineQueryResultDocument),
+    #[serde(rename = "photo")]
+    CachedPhoto(InlineQueryResultCachedPhoto),
+    #[serde(rename = "document")]
+    CachedDocument(InlineQueryResultCachedDocument),
+    #[serde(rename = "audio")]
+    CachedAudio(InlineQueryResultCachedAudio),
+    #[serde(rename = "voice")]
+    CachedVoice(InlineQueryResultCachedVoice),
+    #[serde(rename = "video")]
+    CachedVideo(InlineQueryResultCachedVideo),
+    #[serde(rename = "gif")]
+    CachedAnimation(InlineQueryResultCachedAnimation),
+    #[serde(rename = "sticker")]
+    CachedSticker(InlineQueryResultCachedSticker),
+}
+
+inline_result!(InlineQueryResultArticle, Article, { title: String, input_message_content: InputMessageContent }, {
+    description: String,
+    url: String,
+    thumbnail_url: String,
+    reply_markup: InlineKeyboardMarkup,
+});
+inline_result!(InlineQueryResultPhoto, Photo, { photo_url: String, thumbnail_url: String }, {
+    photo_width: u32,
+    photo_height: u32,
+    title: String,
+    description: String,
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultDocument, Document, { title: String, document_url: String, mime_type: String }, {
+    description: String,
+    caption: String,
+    parse_mode: ParseMode,
+    thumbnail_url: String,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedPhoto, CachedPhoto, { photo_file_id: String }, {
+    title: String,
+    description: String,
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedDocument, CachedDocument, { title: String, document_file_id: String }, {
+    description: String,
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedAudio, CachedAudio, { audio_file_id: String }, {
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedVoice, CachedVoice, { voice_file_id: String, title: String }, {
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedVideo, CachedVideo, { video_file_id: String, title: String }, {
+    description: String,
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedAnimation, CachedAnimation, { gif_file_id: String }, {
+    title: String,
+    caption: String,
+    parse_mode: ParseMode,
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+inline_result!(InlineQueryResultCachedSticker, CachedSticker, { sticker_file_id: String }, {
+    reply_markup: InlineKeyboardMarkup,
+    input_message_content: InputMessageContent,
+});
+
+/// A button shown above inline results, opening a private chat with the bot.
+#[allow(dead_code)]
+#[derive(Serialize, Debug, Clone)]
+pub struct InlineQueryResultsButton {
+    pub text: String,
+    /// Passed to `/start` when the button is pressed.
+    pub start_parameter: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Telegram omits empty fields rather than sending null, so this turns a
+    // serialized struct back into what Telegram sent.
+    fn strip_nulls(value: Value) -> Value {
+        match value {
+            Value::Object(map) => map.into_iter().filter(|(_, v)| !v.is_null()).map(|(k, v)| (k, strip_nulls(v))).collect(),
+            Value::Array(items) => items.into_iter().map(strip_nulls).collect(),
+            other => other,
+        }
+    }
+
+    fn message(extra: serde_json::Value) -> Message {
+        let mut m = json!({ "message_id": 1, "date": 0, "chat": { "id": -100, "type": "supergroup", "title": "Group" } });
+        m.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(m).unwrap()
+    }
+
+    #[test]
+    fn classifies_messages() {
+        let photo = message(json!({
+            "photo": [
+                { "file_id": "small", "file_unique_id": "s", "width": 90, "height": 90 },
+                { "file_id": "large", "file_unique_id": "l", "width": 800, "height": 600 },
+            ],
+            "caption": "look",
+            "media_group_id": "album",
+        }));
+        assert_eq!(photo.kind(), MessageKind::Photo);
+        assert_eq!(photo.text_or_caption(), Some("look"));
+        assert_eq!(photo.file_id(), Some("large"));
+
+        let gif = message(json!({
+            "animation": { "file_id": "anim", "file_unique_id": "a", "width": 1, "height": 1, "duration": 2 },
+            "document": { "file_id": "anim", "file_unique_id": "a" },
+        }));
+        assert_eq!(gif.kind(), MessageKind::Animation);
+
+        let joined = message(json!({ "new_chat_members": [{ "id": 5, "is_bot": false, "first_name": "New" }] }));
+        assert_eq!(joined.kind(), MessageKind::Service);
+        assert_eq!(message(json!({})).kind(), MessageKind::Other);
+    }
+
+    #[test]
+    fn parses_replies_and_forwards() {
+        let msg = message(json!({
+            "text": "/get key",
+            "entities": [{ "type": "bot_command", "offset": 0, "length": 4 }],
+            "message_thread_id": 3,
+            "reply_to_message": { "message_id": 0, "date": 0, "chat": { "id": -100 }, "voice": { "file_id": "v", "file_unique_id": "v", "duration": 4 } },
+            "forward_origin": { "type": "channel", "date": 0, "chat": { "id": -200, "type": "channel" }, "message_id": 9 },
+        }));
+        assert_eq!(msg.text_or_caption_entities()[0].kind, "bot_command");
+        assert_eq!(msg.reply_to_message.as_ref().map(|m| m.kind()), Some(MessageKind::Voice));
+        assert!(matches!(msg.forward_origin.as_deref(), Some(&MessageOrigin::Channel { message_id: 9, .. })));
+        assert_eq!(msg.chat.title.as_deref(), Some("Group"));
+    }
+
+    #[test]
+    fn parses_updates_on_a_small_stack() {
+        let msg = json!({ "message_id": 2, "date": 0, "chat": { "id": 5, "type": "private" }, "text": "hi" });
+        let update = json!({
+            "update_id": 7,
+            "message": { "message_id": 3, "date": 0, "chat": { "id": 5, "type": "private" }, "reply_to_message": msg, "pinned_message": msg },
+        })
+        .to_string();
+        let parsed = std::thread::Builder::new()
+            .stack_size(512 * 1024)
+            .spawn(move || serde_json::from_str::<Update>(&update).map(|u| u.update_id).ok())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(parsed, Some(7));
+    }
+
+    #[test]
+    fn keeps_unknown_fields() {
+        let sent = json!({
+            "message_id": 1,
+            "date": 0,
+            "chat": { "id": 42, "type": "private", "accent_color_id": 3 },
+            "text": "hi",
+            "link_preview_options": { "is_disabled": true },
+            "forward_origin": { "type": "from_the_future", "date": 0 },
+        });
+        let msg: Message = serde_json::from_value(sent.clone()).unwrap();
+        assert_eq!(msg.chat.extra["accent_color_id"], 3);
+        assert!(matches!(msg.forward_origin.as_deref(), Some(MessageOrigin::Unknown(v)) if v["type"] == "from_the_future"));
+        assert!(msg.extra.contains_key("link_preview_options"));
+
+        let back = strip_nulls(serde_json::to_value(&msg).unwrap());
+        assert_eq!(back["chat"], sent["chat"]);
+        assert_eq!(back["forward_origin"], sent["forward_origin"]);
+        assert_eq!(back["link_preview_options"], sent["link_preview_options"]);
+    }
+
+    #[test]
+    fn keeps_unknown_fields_of_tagged_types() {
+        let origin = json!({ "type": "user", "date": 0, "sender_user": { "id": 7, "is_bot": false, "first_name": "A" }, "boost_count": 2 });
+        let parsed: MessageOrigin = serde_json::from_value(origin.clone()).unwrap();
+        assert!(matches!(&parsed, MessageOrigin::User { extra, .. } if extra["boost_count"] == 2));
+        assert_eq!(strip_nulls(serde_json::to_value(&parsed).unwrap())["boost_count"], 2);
+
+        let reaction: ReactionType = serde_json::from_value(json!({ "type": "paid", "stars": 5 })).unwrap();
+        assert_eq!(serde_json::to_value(&reaction).unwrap(), json!({ "type": "paid", "stars": 5 }));
+
+        let future = json!({ "type": "sticker", "sticker_id": "s", "count": 3 });
+        let reaction: ReactionType = serde_json::from_value(future.clone()).unwrap();
+        assert_eq!(reaction, ReactionType::Unknown(future.clone()));
+        assert_eq!(serde_json::to_value(&reaction).unwrap(), future);
+
+        let params: ResponseParameters = serde_json::from_value(json!({ "retry_after": 3, "flood_scope": "chat" })).unwrap();
+        assert_eq!((params.retry_after, &params.extra["flood_scope"]), (Some(3), &json!("chat")));
+    }
+
+    #[test]
+    fn update_keeps_raw_json() {
+        let sent = json!({ "update_id": 9, "business_message": { "message_id": 1, "business_connection_id": "b" } });
+        let update: Update = serde_json::from_value(sent.clone()).unwrap();
+        assert_eq!(update.kind(), None);
+        assert!(update.extra.contains_key("business_message"));
+        assert_eq!(update.raw(), &sent);
+        assert_eq!(serde_json::to_value(&update).unwrap(), sent);
+    }
+
+    #[test]
+    fn messages_keep_their_raw_json() {
+        let sent = json!({ "update_id": 3, "edited_message": { "message_id": 1, "date": 0, "edit_date": 5, "chat": { "id": 42, "type": "private" }, "text": "hi", "future": [1] } });
+        let update: Update = serde_json::from_value(sent.clone()).unwrap();
+        assert_eq!(update.edited_message.as_ref().and_then(|m| m.raw()), Some(&sent["edited_message"]));
+        assert_eq!(message(json!({})).raw(), None);
+    }
+
+    #[test]
+    fn serializes_inline_results() {
+        let cached: InlineQueryResult = InlineQueryResultCachedPhoto::new("1", "AgAD").caption("cat").into();
+        assert_eq!(serde_json::to_value(&cached).unwrap(), json!({ "type": "photo", "id": "1", "photo_file_id": "AgAD", "caption": "cat" }));
+
+        let article: InlineQueryResult = InlineQueryResultArticle::new("2", "Title", InputMessageContent::text("body")).into();
+        assert_eq!(
+            serde_json::to_value(&article).unwrap(),
+            json!({ "type": "article", "id": "2", "title": "Title", "input_message_content": { "message_text": "body" } })
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::ops::Range;
 use std::vec::Vec;
 
 pub fn parse_args(s: &str) -> Vec<String> {
@@ -24,3 +25,115 @@ pub fn parse_args(s: &str) -> Vec<String> {
     if !cur.is_empty() { args.push(cur); }
     args
 }
+
+/// Length of `s` in UTF-16 code units, the unit Telegram uses for message length
+/// limits and entity offsets.
+pub fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Converts a byte range of `text` into a Telegram entity `(offset, length)` pair.
+/// The range must lie on char boundaries.
+#[allow(dead_code)]
+pub fn entity_offset(text: &str, range: Range<usize>) -> (usize, usize) {
+    let offset = utf16_len(&text[..range.start]);
+    let length = utf16_len(&text[range]);
+    (offset, length)
+}
+
+/// Extracts the command name from a message such as `/start@my_bot args`.
+/// Commands addressed to a different bot yield `None`; when the bot's own
+/// username is not known yet, any address is accepted.
+pub fn parse_command(text: &str, bot_username: Option<&str>) -> Option<String> {
+    let head = text.strip_prefix('/')?.split(char::is_whitespace).next()?;
+    let (name, target) = match head.split_once('@') {
+        Some((name, target)) => (name, Some(target)),
+        None => (head, None),
+    };
+    if name.is_empty() {
+        return None;
+    }
+    match (target, bot_username) {
+        (Some(t), Some(me)) if !t.eq_ignore_ascii_case(me) => None,
+        _ => Some(name.to_string()),
+    }
+}
+
+/// Reads JSON from `path`, or `T::default()` if the file does not exist. A file
+/// that does not parse is an error rather than a default that would overwrite
+/// it on the next save.
+pub async fn load_json<T: serde::de::DeserializeOwned + Default>(path: &std::path::Path) -> std::io::Result<T> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes `value` as JSON to a temporary file next to `path`, then renames it
+/// over `path`, so a crash mid-write leaves the previous version intact.
+pub async fn save_json<T: serde::Serialize>(path: &std::path::Path, value: &T) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec_pretty(value)?).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_utf16_code_units() {
+        assert_eq!(utf16_len("hello"), 5);
+        assert_eq!(utf16_len("привет"), 6);
+        // Outside the BMP: one char, a surrogate pair.
+        assert_eq!(utf16_len("😀"), 2);
+        // Man, ZWJ, woman, ZWJ, girl: one grapheme, three surrogate pairs and two joiners.
+        assert_eq!(utf16_len("👨‍👩‍👧"), 8);
+        // Flag: two regional indicators.
+        assert_eq!(utf16_len("🇺🇦"), 4);
+        // e + combining acute accent: one grapheme, two code units.
+        assert_eq!(utf16_len("e\u{301}"), 2);
+        assert_eq!(utf16_len(""), 0);
+    }
+
+    #[test]
+    fn converts_byte_ranges_to_entity_offsets() {
+        let text = "hi 😀 bold";
+        let start = text.find("bold").unwrap();
+        assert_eq!(entity_offset(text, start..start + 4), (6, 4));
+
+        let text = "👨‍👩‍👧 family";
+        let end = "👨‍👩‍👧".len();
+        assert_eq!(entity_offset(text, 0..end), (0, 8));
+        assert_eq!(entity_offset(text, end + 1..text.len()), (9, 6));
+
+        let text = "cafe\u{301} time";
+        let start = text.find("time").unwrap();
+        assert_eq!(entity_offset(text, 0..start - 1), (0, 5));
+        assert_eq!(entity_offset(text, start..text.len()), (6, 4));
+    }
+
+    #[test]
+    fn parses_addressed_commands() {
+        let me = Some("weather_bot");
+        assert_eq!(parse_command("/start", me).as_deref(), Some("start"));
+        assert_eq!(parse_command("/start@weather_bot", me).as_deref(), Some("start"));
+        assert_eq!(parse_command("/start@Weather_Bot", me).as_deref(), Some("start"));
+        assert_eq!(parse_command("/set@weather_bot key value", me).as_deref(), Some("set"));
+        assert_eq!(parse_command("/start@OtherBot", me), None);
+        assert_eq!(parse_command("/start@OtherBot args", me), None);
+        // Before getMe has answered, any address is accepted.
+        assert_eq!(parse_command("/start@OtherBot", None).as_deref(), Some("start"));
+
+        assert_eq!(parse_command("/", me), None);
+        assert_eq!(parse_command("/ start", me), None);
+        assert_eq!(parse_command("/@weather_bot", me), None);
+        assert_eq!(parse_command("start", me), None);
+        assert_eq!(parse_command("", me), None);
+    }
+}
@@ -0,0 +1,203 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use http_body::{LengthLimitError, Limited};
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use hyper::server::conn::AddrIncoming;
+use hyper::service::{make_service_fn, service_fn};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+use crate::types::Update;
+
+const SECRET_HEADER: &str = "x-telegram-bot-api-secret-token";
+
+/// Updates are a few KB at most; anything much larger is not from Telegram.
+pub const MAX_BODY_BYTES: usize = 1 << 20;
+
+pub struct WebhookConfig {
+    pub listen: SocketAddr,
+    pub path: String,
+    /// Required: without it anyone who finds the URL could post made-up
+    /// updates, payments included.
+    pub secret: String,
+}
+
+impl WebhookConfig {
+    /// A secret for one run, for when `WEBHOOK_SECRET` is not set. Telegram
+    /// gets it with `setWebhook`, so it does not need to outlive the process.
+    pub fn random_secret() -> Result<String, getrandom::Error> {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes)?;
+        Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+pub struct WebhookServer {
+    incoming: AddrIncoming,
+    config: Arc<WebhookConfig>,
+}
+
+impl WebhookServer {
+    pub fn bind(config: WebhookConfig) -> Result<Self, hyper::Error> {
+        let incoming = AddrIncoming::bind(&config.listen)?;
+        Ok(Self { incoming, config: Arc::new(config) })
+    }
+
+    pub fn spawn(self, tx: mpsc::Sender<Update>) {
+        let config = self.config.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let config = config.clone();
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    handle(config.clone(), tx.clone(), req)
+                }))
+            }
+        });
+        info!("webhook listener on {}{}", self.incoming.local_addr(), self.config.path);
+        let server = Server::builder(self.incoming).serve(make_svc);
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                tracing::error!("webhook server error: {}", e);
+            }
+        });
+    }
+}
+
+fn reply(status: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = status;
+    resp
+}
+
+async fn handle(config: Arc<WebhookConfig>, tx: mpsc::Sender<Update>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.uri().path() != config.path {
+        return Ok(reply(StatusCode::NOT_FOUND));
+    }
+    if req.method() != Method::POST {
+        return Ok(reply(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let got = req.headers().get(SECRET_HEADER).and_then(|v| v.to_str().ok());
+    if got != Some(config.secret.as_str()) {
+        warn!("webhook request with missing or wrong secret token");
+        return Ok(reply(StatusCode::UNAUTHORIZED));
+    }
+
+    let declared = req.headers().get(CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > MAX_BODY_BYTES) {
+        warn!("webhook body of {} bytes is over the limit", declared.unwrap_or(0));
+        return Ok(reply(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let body = match hyper::body::to_bytes(Limited::new(req.into_body(), MAX_BODY_BYTES)).await {
+        Ok(b) => b,
+        Err(e) if e.is::<LengthLimitError>() => {
+            warn!("webhook body is over the limit of {} bytes", MAX_BODY_BYTES);
+            return Ok(reply(StatusCode::PAYLOAD_TOO_LARGE));
+        }
+        Err(e) => {
+            warn!("failed to read webhook body: {}", e);
+            return Ok(reply(StatusCode::BAD_REQUEST));
+        }
+    };
+
+    // Telegram keeps redelivering anything that is not a 2xx, so an update we
+    // cannot parse is logged and acknowledged instead of blocking the queue.
+    match serde_json::from_slice::<Update>(&body) {
+        Ok(u) => {
+            if tx.send(u).await.is_err() {
+                return Ok(reply(StatusCode::SERVICE_UNAVAILABLE));
+            }
+        }
+        Err(e) => warn!("failed to parse webhook update: {}", e),
+    }
+    Ok(reply(StatusCode::OK))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UPDATE: &str = r#"{"update_id": 7, "message": {"message_id": 1, "date": 0, "chat": {"id": 5, "type": "private"}, "text": "hi"}}"#;
+
+    const SECRET: &str = "s3cret";
+
+    fn config() -> Arc<WebhookConfig> {
+        Arc::new(WebhookConfig { listen: ([127, 0, 0, 1], 0).into(), path: "/hook".into(), secret: SECRET.into() })
+    }
+
+    fn request(method: Method, path: &str, secret: Option<&str>, body: &str) -> Request<Body> {
+        let mut req = Request::builder().method(method).uri(path);
+        if let Some(s) = secret {
+            req = req.header(SECRET_HEADER, s);
+        }
+        req.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn status(config: Arc<WebhookConfig>, req: Request<Body>) -> (StatusCode, Option<Update>) {
+        let (tx, mut rx) = mpsc::channel(1);
+        let resp = handle(config, tx, req).await.unwrap();
+        (resp.status(), rx.try_recv().ok())
+    }
+
+    #[tokio::test]
+    async fn checks_secret_token() {
+        let (code, update) = status(config(), request(Method::POST, "/hook", None, UPDATE)).await;
+        assert_eq!(code, StatusCode::UNAUTHORIZED);
+        assert!(update.is_none());
+
+        let (code, update) = status(config(), request(Method::POST, "/hook", Some("wrong"), UPDATE)).await;
+        assert_eq!(code, StatusCode::UNAUTHORIZED);
+        assert!(update.is_none());
+
+        let (code, update) = status(config(), request(Method::POST, "/hook", Some(SECRET), UPDATE)).await;
+        assert_eq!(code, StatusCode::OK);
+        assert_eq!(update.map(|u| u.update_id), Some(7));
+    }
+
+    #[tokio::test]
+    async fn checks_path_and_method() {
+        let (code, update) = status(config(), request(Method::POST, "/other", Some(SECRET), UPDATE)).await;
+        assert_eq!(code, StatusCode::NOT_FOUND);
+        assert!(update.is_none());
+
+        let (code, _) = status(config(), request(Method::GET, "/hook", Some(SECRET), "")).await;
+        assert_eq!(code, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn acknowledges_unparseable_updates() {
+        let (code, update) = status(config(), request(Method::POST, "/hook", Some(SECRET), "not json")).await;
+        assert_eq!(code, StatusCode::OK);
+        assert!(update.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_bodies() {
+        let big = " ".repeat(MAX_BODY_BYTES + 1);
+        let (code, update) = status(config(), request(Method::POST, "/hook", Some(SECRET), &big)).await;
+        assert_eq!(code, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(update.is_none());
+
+        // A chunked body has no Content-Length up front.
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..=MAX_BODY_BYTES / 1024 {
+                if sender.send_data(vec![b' '; 1024].into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let req = Request::builder().method(Method::POST).uri("/hook").header(SECRET_HEADER, SECRET).body(body).unwrap();
+        let (code, _) = status(config(), req).await;
+        assert_eq!(code, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn random_secrets_are_valid_and_differ() {
+        let a = WebhookConfig::random_secret().unwrap();
+        assert_eq!(a.len(), 64);
+        assert!(a.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(a, WebhookConfig::random_secret().unwrap());
+    }
+}